@@ -48,6 +48,7 @@ impl Hittable for XyRect {
         rec.v = (y - self.y0) / (self.y1 - self.y0);
         rec.t = t;
         rec.set_face_normal(r, &Vec3::new(0.0, 0.0, 1.0));
+        rec.set_tangents(Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, self.y1 - self.y0, 0.0));
         rec.mat_ptr = Option::Some(self.mp.clone());
         rec.p = r.at(t);
 
@@ -101,6 +102,7 @@ impl Hittable for XzRect {
         rec.v = (z - self.z0) / (self.z1 - self.z0);
         rec.t = t;
         rec.set_face_normal(r, &Vec3::new(0.0, 1.0, 0.0));
+        rec.set_tangents(Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
         rec.mat_ptr = Option::Some(self.mp.clone());
         rec.p = r.at(t);
 
@@ -154,6 +156,7 @@ impl Hittable for YzRect {
         rec.v = (z - self.z0) / (self.z1 - self.z0);
         rec.t = t;
         rec.set_face_normal(r, &Vec3::new(1.0, 0.0, 0.0));
+        rec.set_tangents(Vec3::new(0.0, self.y1 - self.y0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
         rec.mat_ptr = Option::Some(self.mp.clone());
         rec.p = r.at(t);
 
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::rc::Rc;
+
+use crate::color::{Color, ColorSpace};
+use crate::film::Film;
+use crate::hittable::HitRecord;
+
+pub struct Aovs {
+    pub normal: Film,
+    pub position: Film,
+    pub depth: Film,
+    pub uv: Film,
+    pub albedo: Film,
+    pub object_id: Film,
+    pub material_id: Film,
+    material_ids: HashMap<usize, u32>,
+}
+
+impl Aovs {
+    pub fn new(width: u32, height: u32) -> Self {
+        Aovs {
+            normal: Film::new(width, height),
+            position: Film::new(width, height),
+            depth: Film::new(width, height),
+            uv: Film::new(width, height),
+            albedo: Film::new(width, height),
+            object_id: Film::new(width, height),
+            material_id: Film::new(width, height),
+            material_ids: HashMap::new(),
+        }
+    }
+
+    // Averages the first hit of each camera sample into the pixel. Ids are taken from the first
+    // sample only, since averaging them is meaningless.
+    pub fn add_sample(&mut self, x: u32, y: u32, rec: Option<&HitRecord>, weight: f64, first: bool, working_space: ColorSpace) {
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return,
+        };
+
+        let material = rec.mat_ptr.as_ref().unwrap();
+        let albedo = working_space.linear_srgb_to_working(&material.albedo(rec));
+
+        self.normal.add(x, y, weight * material.shading_normal(rec));
+        self.position.add(x, y, weight * rec.p);
+        self.depth.add(x, y, weight * Color::new(rec.t, rec.t, rec.t));
+        self.uv.add(x, y, weight * Color::new(rec.u, rec.v, 0.0));
+        self.albedo.add(x, y, weight * albedo);
+
+        if first {
+            let next_id = self.material_ids.len() as u32 + 1;
+            let key = Rc::as_ptr(material) as *const u8 as usize;
+            let material_id = *self.material_ids.entry(key).or_insert(next_id) as f64;
+            let object_id = rec.object_id as f64;
+
+            self.object_id.set(x, y, Color::new(object_id, object_id, object_id));
+            self.material_id.set(x, y, Color::new(material_id, material_id, material_id));
+        }
+    }
+
+    pub fn write(&self, prefix: &str) -> std::io::Result<()> {
+        let passes = [
+            ("normal", &self.normal),
+            ("position", &self.position),
+            ("depth", &self.depth),
+            ("uv", &self.uv),
+            ("albedo", &self.albedo),
+            ("object_id", &self.object_id),
+            ("material_id", &self.material_id),
+        ];
+
+        for (name, film) in passes.iter() {
+            let mut f = BufWriter::new(File::create(format!("{}.{}.pfm", prefix, name))?);
+            film.write_pfm(&mut f)?;
+        }
+
+        Ok(())
+    }
+}
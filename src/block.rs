@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use crate::aabb::Aabb;
 use crate::aarect::{XyRect, XzRect, YzRect};
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{FlipFace, HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::material::Material;
 use crate::point3::Point3;
@@ -18,14 +18,15 @@ impl Block {
     pub fn new(p0: Point3, p1: Point3, ptr: Rc<dyn Material>) -> Self {
         let mut sides = HittableList::new();
 
+        // The rects face +x, +y and +z, so the sides at the low corner are flipped to face outwards.
         sides.add(Rc::new(XyRect::new(p0.x(), p1.x(), p0.y(), p1.y(), p1.z(), ptr.clone())));
-        sides.add(Rc::new(XyRect::new(p0.x(), p1.x(), p0.y(), p1.y(), p0.z(), ptr.clone())));
+        sides.add(Rc::new(FlipFace::new(Rc::new(XyRect::new(p0.x(), p1.x(), p0.y(), p1.y(), p0.z(), ptr.clone())))));
 
         sides.add(Rc::new(XzRect::new(p0.x(), p1.x(), p0.z(), p1.z(), p1.y(), ptr.clone())));
-        sides.add(Rc::new(XzRect::new(p0.x(), p1.x(), p0.z(), p1.z(), p0.y(), ptr.clone())));
+        sides.add(Rc::new(FlipFace::new(Rc::new(XzRect::new(p0.x(), p1.x(), p0.z(), p1.z(), p0.y(), ptr.clone())))));
 
         sides.add(Rc::new(YzRect::new(p0.y(), p1.y(), p0.z(), p1.z(), p1.x(), ptr.clone())));
-        sides.add(Rc::new(YzRect::new(p0.y(), p1.y(), p0.z(), p1.z(), p0.x(), ptr.clone())));
+        sides.add(Rc::new(FlipFace::new(Rc::new(YzRect::new(p0.y(), p1.y(), p0.z(), p1.z(), p0.x(), ptr.clone())))));
 
         Block {
             box_min: p0,
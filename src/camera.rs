@@ -1,21 +1,501 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use crate::color::clamp;
 use crate::point3::Point3;
-use crate::random::random_in_range;
+use crate::random::random;
 use crate::ray::Ray;
+use crate::texture::Texture;
 use crate::vec3::Vec3;
 
+// Maps a point on the image, with `s` and `t` in [0, 1] from the lower left corner, to a ray in
+// camera space, where the camera looks down -z with +y up.
+pub trait Projection {
+    // Returns None where the image has no rays, such as outside a fisheye's image circle.
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)>;
+
+    // Width and spread angle of the cone covered by one pixel's rays, used for texture filtering.
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64);
+}
+
+// The shape of the lens opening, which sets the shape of out of focus highlights.
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circle,
+    // A regular polygon with one corner at `rotation` degrees.
+    Polygon { blades: u32, rotation: f64 },
+    // An aperture mask, tabulated so that points are drawn in proportion to its brightness. The
+    // image is inscribed in the lens, so its corners are cut off.
+    Image { cdf: Rc<Vec<f64>>, resolution: usize },
+}
+
+impl ApertureShape {
+    // Fails if the mask is black everywhere within the lens.
+    pub fn from_texture(texture: &dyn Texture, resolution: usize) -> Result<Self, String> {
+        let mut cdf = Vec::with_capacity(resolution * resolution);
+        let mut total = 0.0;
+        for j in 0..resolution {
+            for i in 0..resolution {
+                let u = (i as f64 + 0.5) / resolution as f64;
+                let v = (j as f64 + 0.5) / resolution as f64;
+                if (2.0 * u - 1.0).powi(2) + (2.0 * v - 1.0).powi(2) <= 1.0 {
+                    total += f64::max(texture.scalar_value(u, v, &Point3::new(u, v, 0.0)), 0.0);
+                }
+                cdf.push(total);
+            }
+        }
+        if total <= 0.0 {
+            return Err(String::from("An aperture image must let some light through the lens"));
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Ok(ApertureShape::Image { cdf: Rc::new(cdf), resolution })
+    }
+
+    // A point on the aperture, within the unit disk.
+    fn sample(&self) -> Vec3 {
+        match self {
+            ApertureShape::Circle => Vec3::random_in_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => {
+                // Pick one of the triangles fanning out from the centre, then a point within it.
+                let sector = 2.0 * PI / *blades as f64;
+                let k = f64::floor(random() * *blades as f64);
+                let a0 = f64::to_radians(*rotation) + k * sector;
+                let (r1, r2) = (f64::sqrt(random()), random());
+                let corner = |a: f64| Vec3::new(f64::cos(a), f64::sin(a), 0.0);
+                r1 * ((1.0 - r2) * corner(a0) + r2 * corner(a0 + sector))
+            }
+            ApertureShape::Image { cdf, resolution } => {
+                let u = random();
+                let cell = cdf.partition_point(|c| *c <= u).min(cdf.len() - 1);
+                // Cells on the rim are only partly inside the lens, so points outside are redrawn.
+                loop {
+                    let x = ((cell % resolution) as f64 + random()) / *resolution as f64;
+                    let y = ((cell / resolution) as f64 + random()) / *resolution as f64;
+                    let p = Vec3::new(2.0 * x - 1.0, 2.0 * y - 1.0, 0.0);
+                    if p.length_squared() <= 1.0 {
+                        return p;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Lens settings beyond a plain thin lens. `cats_eye` is how far the lens barrel clips the aperture
+// of off-axis pixels, in aperture radii at the top of the image. `shift` moves the image in
+// fractions of its height, and `tilt` turns the plane of focus about the horizontal axis, in degrees.
+#[derive(Clone)]
+pub struct Lens {
+    pub aperture_shape: ApertureShape,
+    pub cats_eye: f64,
+    pub shift: (f64, f64),
+    pub tilt: f64,
+}
+
+impl Lens {
+    pub fn new() -> Self {
+        Lens {
+            aperture_shape: ApertureShape::Circle,
+            cats_eye: 0.0,
+            shift: (0.0, 0.0),
+            tilt: 0.0,
+        }
+    }
+
+    pub fn with_aperture_shape(self, aperture_shape: ApertureShape) -> Self {
+        Lens { aperture_shape, ..self }
+    }
+
+    pub fn with_cats_eye(self, cats_eye: f64) -> Self {
+        Lens { cats_eye, ..self }
+    }
+
+    pub fn with_shift(self, shift: (f64, f64)) -> Self {
+        Lens { shift, ..self }
+    }
+
+    pub fn with_tilt(self, tilt: f64) -> Self {
+        Lens { tilt, ..self }
+    }
+}
+
+pub struct Perspective {
+    viewport_width: f64,
+    viewport_height: f64,
+    lens_radius: f64,
+    focus_dist: f64,
+    lens: Lens,
+}
+
+impl Perspective {
+    pub fn new(fov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64) -> Self {
+        let theta = f64::to_radians(fov);
+        let h = f64::tan(theta / 2.0);
+        let viewport_height = 2.0 * h;
+
+        Perspective {
+            viewport_width: aspect_ratio * viewport_height,
+            viewport_height,
+            lens_radius: aperture / 2.0,
+            focus_dist,
+            lens: Lens::new(),
+        }
+    }
+
+    pub fn with_lens(self, lens: Lens) -> Self {
+        Perspective { lens, ..self }
+    }
+}
+
+impl Projection for Perspective {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        let x = (s - 0.5) * self.viewport_width;
+        let y = (t - 0.5) * self.viewport_height;
+
+        // Off axis, the rear of the lens barrel cuts into the aperture, and rays it blocks are lost.
+        let rd = self.lens.aperture_shape.sample();
+        let barrel = self.lens.cats_eye * 2.0 / self.viewport_height * Vec3::new(x, y, 0.0);
+        if (rd + barrel).length_squared() > 1.0 {
+            return None;
+        }
+        let offset = self.lens_radius * rd;
+
+        // The chief ray through the centre of the lens meets the plane of focus, which the tilt
+        // turns about the horizontal axis.
+        let chief = Vec3::new(
+            x + self.lens.shift.0 * self.viewport_height,
+            y + self.lens.shift.1 * self.viewport_height,
+            -1.0,
+        );
+        // Rays are scaled to reach the plane of focus at `focus_dist` times the chief ray, so chief
+        // rays that run parallel to the plane, or meet it behind the camera, are focused at infinity.
+        let tilt = f64::to_radians(self.lens.tilt);
+        let normal = Vec3::new(0.0, f64::sin(tilt), f64::cos(tilt));
+        let inverse_distance = f64::max(-Vec3::dot(&normal, &chief) / (self.focus_dist * normal.z()), 0.0);
+        Some((offset, self.focus_dist * (chief - inverse_distance * offset)))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        (0.0, self.viewport_height / image_height as f64)
+    }
+}
+
+// Parallel projection, for elevations and plans. `view_width` is the width of the scene covered by
+// the image.
+pub struct Orthographic {
+    view_width: f64,
+    view_height: f64,
+}
+
+impl Orthographic {
+    pub fn new(view_width: f64, aspect_ratio: f64) -> Self {
+        Orthographic {
+            view_width,
+            view_height: view_width / aspect_ratio,
+        }
+    }
+}
+
+impl Projection for Orthographic {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        let origin = Point3::new((s - 0.5) * self.view_width, (t - 0.5) * self.view_height, 0.0);
+        Some((origin, Vec3::new(0.0, 0.0, -1.0)))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        (self.view_height / image_height as f64, 0.0)
+    }
+}
+
+// Latitude-longitude projection over the full sphere, with the view direction in the middle of the
+// image. The image should be twice as wide as it is high.
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = Vec3::new(
+            f64::cos(latitude) * f64::sin(longitude),
+            f64::sin(latitude),
+            -f64::cos(latitude) * f64::cos(longitude),
+        );
+        Some((Point3::new(0.0, 0.0, 0.0), direction))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        (0.0, PI / image_height as f64)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum FisheyeMapping {
+    // The distance from the centre of the image is proportional to the angle off axis.
+    Equidistant,
+    // Each pixel covers the same solid angle.
+    Equisolid,
+}
+
+// A circular fisheye whose image circle fills the height of the image.
+pub struct Fisheye {
+    mapping: FisheyeMapping,
+    fov: f64,
+    aspect_ratio: f64,
+}
+
+impl Fisheye {
+    pub fn new(mapping: FisheyeMapping, fov: f64, aspect_ratio: f64) -> Self {
+        Fisheye {
+            mapping,
+            fov: f64::to_radians(fov),
+            aspect_ratio,
+        }
+    }
+}
+
+impl Projection for Fisheye {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let r = f64::sqrt(x * x + y * y);
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.fov / 2.0,
+            FisheyeMapping::Equisolid => 2.0 * f64::asin(r * f64::sin(self.fov / 4.0)),
+        };
+        let (sin_phi, cos_phi) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        let direction = Vec3::new(f64::sin(theta) * cos_phi, f64::sin(theta) * sin_phi, -f64::cos(theta));
+        Some((Point3::new(0.0, 0.0, 0.0), direction))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        (0.0, self.fov / image_height as f64)
+    }
+}
+
+// Six 90 degree faces laid out in a 3x2 grid: +x, -x, +y along the top row and -y, +z, -z along the
+// bottom, in camera space. Each face is given as its forward, right and up directions.
+const CUBEMAP_FACES: [[(f64, f64, f64); 3]; 6] = [
+    [(1.0, 0.0, 0.0), (0.0, 0.0, 1.0), (0.0, 1.0, 0.0)],
+    [(-1.0, 0.0, 0.0), (0.0, 0.0, -1.0), (0.0, 1.0, 0.0)],
+    [(0.0, 1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, 1.0)],
+    [(0.0, -1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, -1.0)],
+    [(0.0, 0.0, 1.0), (-1.0, 0.0, 0.0), (0.0, 1.0, 0.0)],
+    [(0.0, 0.0, -1.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)],
+];
+
+// Renders the six faces of a cube map into one image, which should be 3:2.
+pub struct Cubemap;
+
+impl Projection for Cubemap {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        let (fs, ft) = (3.0 * clamp(s, 0.0, 0.999_999), 2.0 * clamp(t, 0.0, 0.999_999));
+        let (column, row) = (fs.floor(), ft.floor());
+        let face = CUBEMAP_FACES[3 * (1 - row as usize) + column as usize];
+
+        let a = 2.0 * (fs - column) - 1.0;
+        let b = 2.0 * (ft - row) - 1.0;
+        let [forward, right, up] = face;
+        let direction = Vec3::new(forward.0, forward.1, forward.2)
+            + a * Vec3::new(right.0, right.1, right.2)
+            + b * Vec3::new(up.0, up.1, up.2);
+        Some((Point3::new(0.0, 0.0, 0.0), direction))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        (0.0, 4.0 / image_height as f64)
+    }
+}
+
+// The panoramic projections, which can replace a scene's camera from the command line.
+#[derive(Clone, Copy)]
+pub enum Panorama {
+    Equirectangular,
+    Fisheye(FisheyeMapping, f64),
+    Cubemap,
+}
+
+impl Panorama {
+    pub fn from_name(name: &str, fov: f64) -> Option<Self> {
+        match name {
+            "equirectangular" => Some(Panorama::Equirectangular),
+            "fisheye" | "fisheye-equidistant" => Some(Panorama::Fisheye(FisheyeMapping::Equidistant, fov)),
+            "fisheye-equisolid" => Some(Panorama::Fisheye(FisheyeMapping::Equisolid, fov)),
+            "cubemap" => Some(Panorama::Cubemap),
+            _ => None,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        match self {
+            Panorama::Equirectangular => 2.0,
+            Panorama::Fisheye(..) => 1.0,
+            Panorama::Cubemap => 1.5,
+        }
+    }
+
+    pub fn projection(&self) -> Rc<dyn Projection> {
+        match *self {
+            Panorama::Equirectangular => Rc::new(Equirectangular),
+            Panorama::Fisheye(mapping, fov) => Rc::new(Fisheye::new(mapping, fov, self.aspect_ratio())),
+            Panorama::Cubemap => Rc::new(Cubemap),
+        }
+    }
+}
+
+// Where the lens is focused.
+#[derive(Clone, Copy)]
+pub enum Focus {
+    Distance(f64),
+    // Focuses on the plane through a point, square to the view direction.
+    Point(Point3),
+    LookAt,
+}
+
+impl Focus {
+    pub fn distance(&self, lookfrom: Point3, lookat: Point3) -> f64 {
+        match *self {
+            Focus::Distance(d) => d,
+            Focus::Point(p) => Vec3::dot(&(p - lookfrom), &Vec3::unit_vector(lookat - lookfrom)),
+            Focus::LookAt => (lookat - lookfrom).length(),
+        }
+    }
+}
+
+// A camera set up the way a photographer would. Lengths on the sensor are in millimetres, the
+// shutter is open for `shutter` seconds, and scene time counts frames at `frame_rate`.
+// `scene_scale` is the number of scene units in a metre.
+#[derive(Clone, Copy)]
+pub struct PhysicalCamera {
+    pub focal_length: f64,
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    pub shutter: f64,
+    pub iso: f64,
+    pub frame_rate: f64,
+    pub scene_scale: f64,
+}
+
+impl PhysicalCamera {
+    // A full frame sensor with a 180 degree shutter at 24 frames per second, at ISO 100.
+    pub fn new(focal_length: f64, f_number: f64) -> Self {
+        PhysicalCamera {
+            focal_length,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number,
+            shutter: 1.0 / 48.0,
+            iso: 100.0,
+            frame_rate: 24.0,
+            scene_scale: 1.0,
+        }
+    }
+
+    pub fn with_sensor(self, sensor_width: f64, sensor_height: f64) -> Self {
+        PhysicalCamera { sensor_width, sensor_height, ..self }
+    }
+
+    pub fn with_shutter(self, shutter: f64) -> Self {
+        PhysicalCamera { shutter, ..self }
+    }
+
+    pub fn with_iso(self, iso: f64) -> Self {
+        PhysicalCamera { iso, ..self }
+    }
+
+    pub fn with_frame_rate(self, frame_rate: f64) -> Self {
+        PhysicalCamera { frame_rate, ..self }
+    }
+
+    pub fn with_scene_scale(self, scene_scale: f64) -> Self {
+        PhysicalCamera { scene_scale, ..self }
+    }
+
+    // The image fills the sensor, cropping whichever side does not fit its aspect ratio.
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let height = f64::min(self.sensor_height, self.sensor_width / aspect_ratio);
+        f64::to_degrees(2.0 * f64::atan(height / (2.0 * self.focal_length)))
+    }
+
+    // Diameter of the entrance pupil, in scene units.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number / 1000.0 * self.scene_scale
+    }
+
+    pub fn shutter_interval(&self) -> f64 {
+        self.shutter * self.frame_rate
+    }
+
+    // Exposure in stops, relative to the defaults at f/8. Scenes are lit to look right without a
+    // physical camera, so the default settings leave their brightness as it is.
+    pub fn exposure(&self) -> f64 {
+        let reference = PhysicalCamera::new(self.focal_length, 8.0);
+        f64::log2(self.light_gathered() / reference.light_gathered())
+    }
+
+    fn light_gathered(&self) -> f64 {
+        self.shutter * self.iso / (self.f_number * self.f_number)
+    }
+}
+
+// How the shutter opens and closes over the exposure, which weights the times that rays are traced
+// at. A trapezoid spends `ramp` of the exposure opening and as long again closing.
+#[derive(Clone, Copy)]
+pub enum ShutterCurve {
+    Box,
+    Triangle,
+    Trapezoid(f64),
+}
+
+impl ShutterCurve {
+    pub fn from_name(name: &str, ramp: f64) -> Option<Self> {
+        match name {
+            "box" => Some(ShutterCurve::Box),
+            "triangle" => Some(ShutterCurve::Triangle),
+            "trapezoid" => Some(ShutterCurve::Trapezoid(ramp)),
+            _ => None,
+        }
+    }
+
+    // Maps a uniform number in [0, 1) to a fraction of the exposure.
+    fn sample(&self, u: f64) -> f64 {
+        let ramp = match *self {
+            ShutterCurve::Box => return u,
+            ShutterCurve::Triangle => 0.5,
+            ShutterCurve::Trapezoid(ramp) => clamp(ramp, 0.0, 0.5),
+        };
+
+        // The ramps each hold ramp / 2 of the area, the plateau 1 - 2 * ramp.
+        let x = u * (1.0 - ramp);
+        if x < ramp / 2.0 {
+            f64::sqrt(2.0 * ramp * x)
+        } else if x < 1.0 - 1.5 * ramp {
+            x + ramp / 2.0
+        } else {
+            1.0 - f64::sqrt(2.0 * ramp * (1.0 - ramp - x))
+        }
+    }
+}
+
 pub struct Camera {
     pub origin: Point3,
-    pub lower_left_corner: Point3,
-    pub horizontal: Vec3,
-    pub vertical: Vec3,
-    pub lens_radius: f64,
     pub u: Vec3,
     pub v: Vec3,
+    pub w: Vec3,
+    pub projection: Rc<dyn Projection>,
     pub time0: f64,
     pub time1: f64,
+    pub shutter_curve: ShutterCurve,
+    pub rolling_shutter: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -27,41 +507,268 @@ impl Camera {
         time0: f64,
         time1: f64,
     ) -> Camera {
-        let theta = f64::to_radians(fov);
-        let h = f64::tan(theta / 2.0);
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
+        let projection = Rc::new(Perspective::new(fov, aspect_ratio, aperture, focus_dist));
+        Camera::new_with_projection(lookfrom, lookat, vup, projection, time0, time1)
+    }
 
+    pub fn new_with_projection(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        projection: Rc<dyn Projection>,
+        time0: f64,
+        time1: f64,
+    ) -> Camera {
         let w = Vec3::unit_vector(lookfrom - lookat);
         let u = Vec3::unit_vector(Vec3::cross(&vup, &w));
         let v = Vec3::cross(&w, &u);
 
-        let origin = lookfrom;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
-
         Camera {
-            origin,
-            horizontal,
-            vertical,
-            lower_left_corner,
-            lens_radius: aperture / 2.0,
+            origin: lookfrom,
             u,
             v,
+            w,
+            projection,
             time0,
             time1,
+            shutter_curve: ShutterCurve::Box,
+            rolling_shutter: 0.0,
+        }
+    }
+
+    pub fn with_shutter_curve(self, shutter_curve: ShutterCurve) -> Self {
+        Camera { shutter_curve, ..self }
+    }
+
+    // With a rolling shutter the rows are exposed one after another from the top of the image,
+    // the last starting `readout` after the first. The readout fits inside the shutter interval, so
+    // each row is exposed for the interval less the readout.
+    pub fn with_rolling_shutter(self, readout: f64) -> Self {
+        Camera { rolling_shutter: readout, ..self }
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (origin, direction) = self.projection.generate_ray(s, t)?;
+        let readout = clamp(self.rolling_shutter, 0.0, self.time1 - self.time0);
+        let time = self.time0
+            + clamp(1.0 - t, 0.0, 1.0) * readout
+            + self.shutter_curve.sample(random()) * (self.time1 - self.time0 - readout);
+
+        Some(Ray::new(
+            self.origin + self.to_world(&origin),
+            self.to_world(&direction),
+            time,
+        ))
+    }
+
+    pub fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        self.projection.pixel_footprint(image_height)
+    }
+
+    fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::texture::SolidColor;
+
+    fn ray_direction(projection: &dyn Projection, s: f64, t: f64) -> Vec3 {
+        Vec3::unit_vector(projection.generate_ray(s, t).unwrap().1)
+    }
+
+    fn angle_off_axis(direction: &Vec3) -> f64 {
+        f64::acos(-direction.z())
+    }
+
+    #[test]
+    fn equirectangular_wraps_around_the_view() {
+        let expected = [
+            ((0.5, 0.5), Vec3::new(0.0, 0.0, -1.0)),
+            ((0.0, 0.5), Vec3::new(0.0, 0.0, 1.0)),
+            ((1.0, 0.5), Vec3::new(0.0, 0.0, 1.0)),
+            ((0.75, 0.5), Vec3::new(1.0, 0.0, 0.0)),
+            ((0.25, 0.5), Vec3::new(-1.0, 0.0, 0.0)),
+            ((0.5, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+            ((0.5, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+        ];
+        for ((s, t), direction) in expected.iter() {
+            assert!((ray_direction(&Equirectangular, *s, *t) - *direction).length() < 1e-9);
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
-        let offset = self.u * rd.x() + self.v * rd.y();
+    #[test]
+    fn fisheye_edge_is_half_the_field_of_view() {
+        for &fov in [90.0, 180.0, 220.0].iter() {
+            let equidistant = Fisheye::new(FisheyeMapping::Equidistant, fov, 1.0);
+            let equisolid = Fisheye::new(FisheyeMapping::Equisolid, fov, 1.0);
+            for projection in [&equidistant as &dyn Projection, &equisolid].iter() {
+                assert!((ray_direction(*projection, 0.5, 0.5) - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+                for &(s, t) in [(1.0, 0.5), (0.0, 0.5), (0.5, 1.0), (0.5, 0.0)].iter() {
+                    let angle = angle_off_axis(&ray_direction(*projection, s, t));
+                    assert!(f64::abs(angle - f64::to_radians(fov / 2.0)) < 1e-9);
+                }
+                assert!(projection.generate_ray(1.0, 1.0).is_none());
+            }
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            random_in_range(self.time0, self.time1),
-        )
+            // Equidistant spaces angles evenly, while equisolid squeezes them towards the edge.
+            let half = angle_off_axis(&ray_direction(&equidistant, 0.75, 0.5));
+            assert!(f64::abs(half - f64::to_radians(fov / 4.0)) < 1e-9);
+            assert!(angle_off_axis(&ray_direction(&equisolid, 0.75, 0.5)) < half);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let orthographic = Orthographic::new(4.0, 2.0);
+        for &(s, t) in [(0.0, 0.0), (0.5, 0.5), (1.0, 1.0), (0.25, 0.75)].iter() {
+            let (origin, direction) = orthographic.generate_ray(s, t).unwrap();
+            assert!((origin - Point3::new((s - 0.5) * 4.0, (t - 0.5) * 2.0, 0.0)).length() < 1e-9);
+            assert!((direction - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+        }
+    }
+
+    fn face_vectors(face: &[(f64, f64, f64); 3]) -> [Vec3; 3] {
+        let v = |a: (f64, f64, f64)| Vec3::new(a.0, a.1, a.2);
+        [v(face[0]), v(face[1]), v(face[2])]
+    }
+
+    #[test]
+    fn cubemap_cells_look_along_their_axes() {
+        // In the order the layout lists them, along the top row and then the bottom.
+        let axes = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ];
+        for (k, axis) in axes.iter().enumerate() {
+            let s = ((k % 3) as f64 + 0.5) / 3.0;
+            let t = 1.0 - ((k / 3) as f64 + 0.5) / 2.0;
+            assert!((ray_direction(&Cubemap, s, t) - *axis).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cubemap_faces_meet_their_neighbours_at_shared_edges() {
+        for face in CUBEMAP_FACES.iter() {
+            let [forward, right, up] = face_vectors(face);
+
+            // Each face is seen the right way round, as by a camera looking down -z with +y up.
+            assert!((Vec3::cross(&right, &up) + forward).length() < 1e-9);
+
+            // Along each edge, the face's directions lie on the matching edge of the face beyond it.
+            for (edge, across) in [(right, up), (-right, up), (up, right), (-up, right)].iter() {
+                let neighbour = CUBEMAP_FACES.iter()
+                    .map(face_vectors)
+                    .find(|n| (n[0] - *edge).length() < 1e-9)
+                    .unwrap();
+                for i in 0..=4 {
+                    let b = i as f64 / 2.0 - 1.0;
+                    let p = forward + *edge + b * *across;
+                    let (x, y) = (Vec3::dot(&p, &neighbour[1]), Vec3::dot(&p, &neighbour[2]));
+                    assert!(f64::abs(Vec3::dot(&p, &neighbour[0]) - 1.0) < 1e-9);
+                    assert!(f64::abs(f64::max(x.abs(), y.abs()) - 1.0) < 1e-9);
+                    assert!(f64::abs(x) <= 1.0 + 1e-9 && f64::abs(y) <= 1.0 + 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn image_aperture_lies_within_the_lens() {
+        let white = SolidColor::new(Color::new(1.0, 1.0, 1.0));
+        let lens = Lens::new().with_aperture_shape(ApertureShape::from_texture(&white, 16).unwrap());
+        for _ in 0..10000 {
+            assert!(lens.aperture_shape.sample().length_squared() <= 1.0);
+        }
+
+        // So without a cat's eye, nothing on the axis is blocked.
+        let perspective = Perspective::new(90.0, 1.0, 1.0, 1.0).with_lens(lens);
+        assert!((0..1000).all(|_| perspective.generate_ray(0.5, 0.5).is_some()));
+    }
+
+    #[test]
+    fn black_aperture_image_is_rejected() {
+        let black = SolidColor::new(Color::new(0.0, 0.0, 0.0));
+        assert!(ApertureShape::from_texture(&black, 16).is_err());
+    }
+
+    #[test]
+    fn steep_tilt_keeps_rays_in_front_of_the_camera() {
+        // At 60 degrees, chief rays above 30 degrees over the axis never meet the plane of focus.
+        let perspective = Perspective::new(90.0, 1.0, 1.0, 2.0).with_lens(Lens::new().with_tilt(60.0));
+        for j in 0..=20 {
+            let t = j as f64 / 20.0;
+            let (origin, direction) = perspective.generate_ray(0.5, t).unwrap();
+            assert!(direction.z() < 0.0 && direction.length().is_finite());
+
+            // Where the plane of focus is out of reach, the rays run parallel to the chief ray.
+            let y = t - 0.5;
+            if 2.0 * y >= f64::tan(f64::to_radians(30.0)) {
+                let chief = Vec3::new(0.0, 2.0 * y, -1.0);
+                assert!(Vec3::cross(&direction, &chief).length() < 1e-9 * direction.length());
+            }
+            assert!(origin.length() <= 0.5);
+        }
+    }
+
+    // Checks that `sample` inverts `cdf` across the exposure.
+    fn assert_inverts(curve: ShutterCurve, cdf: impl Fn(f64) -> f64) {
+        for i in 0..=20 {
+            let x = i as f64 / 20.0;
+            assert!(f64::abs(curve.sample(cdf(x)) - x) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn triangle_shutter_inverts_its_cdf() {
+        assert_inverts(ShutterCurve::Triangle, |x| {
+            if x < 0.5 {
+                2.0 * x * x
+            } else {
+                1.0 - 2.0 * (1.0 - x) * (1.0 - x)
+            }
+        });
+    }
+
+    #[test]
+    fn trapezoid_shutter_inverts_its_cdf() {
+        let ramp = 0.2;
+        // The density climbs to `height` over the opening ramp and falls back over the closing one.
+        let height = 1.0 / (1.0 - ramp);
+        assert_inverts(ShutterCurve::Trapezoid(ramp), |x| {
+            if x < ramp {
+                height * x * x / (2.0 * ramp)
+            } else if x < 1.0 - ramp {
+                height * (x - ramp / 2.0)
+            } else {
+                1.0 - height * (1.0 - x) * (1.0 - x) / (2.0 * ramp)
+            }
+        });
+    }
+
+    #[test]
+    fn rolling_shutter_stays_within_the_shutter_interval() {
+        let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 90.0, 1.0, 0.0, 1.0, 0.0, 1.0)
+            .with_rolling_shutter(0.5);
+        for i in 0..1000 {
+            let t = i as f64 / 900.0;
+            let time = camera.get_ray(0.5, t).unwrap().time();
+            assert!((0.0..=1.0).contains(&time));
+        }
+    }
+
+    #[test]
+    fn default_physical_camera_keeps_the_scene_exposure() {
+        let camera = PhysicalCamera::new(35.0, 8.0);
+        assert!(f64::abs(camera.exposure()) < 1e-12);
+        assert!(f64::abs(camera.with_iso(400.0).exposure() - 2.0) < 1e-12);
+        assert!(f64::abs(PhysicalCamera::new(35.0, 4.0).exposure() - 2.0) < 1e-12);
+    }
+}
@@ -0,0 +1,239 @@
+use std::rc::Rc;
+
+use crate::camera::{Camera, Projection};
+use crate::color::clamp;
+use crate::point3::Point3;
+use crate::vec3::Vec3;
+
+// A camera pose at a point in time, which counts frames.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, lookfrom: Point3, lookat: Point3, vfov: f64) -> Self {
+        Keyframe {
+            time,
+            lookfrom,
+            lookat,
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov,
+            focus_dist: (lookat - lookfrom).length(),
+        }
+    }
+
+    pub fn with_vup(self, vup: Vec3) -> Self {
+        Keyframe { vup, ..self }
+    }
+
+    pub fn with_vfov(self, vfov: f64) -> Self {
+        Keyframe { vfov, ..self }
+    }
+
+    pub fn with_focus_dist(self, focus_dist: f64) -> Self {
+        Keyframe { focus_dist, ..self }
+    }
+
+    // A camera in this pose, seeing through `projection`, or without one through a thin lens with
+    // the pose's field of view and focus distance.
+    pub fn camera(&self, projection: Option<Rc<dyn Projection>>, aspect_ratio: f64, aperture: f64, time0: f64, time1: f64) -> Camera {
+        match projection {
+            Some(projection) => Camera::new_with_projection(self.lookfrom, self.lookat, self.vup, projection, time0, time1),
+            None => Camera::new(self.lookfrom, self.lookat, self.vup, self.vfov, aspect_ratio, aperture, self.focus_dist, time0, time1),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Spline {
+    // Passes through every keyframe.
+    CatmullRom,
+    // Keyframes come in runs of four sharing their ends, as in 0-3, 3-6 and so on: the camera
+    // passes through the ends, and the two keyframes between them are handles whose times are
+    // ignored.
+    Bezier,
+}
+
+impl Spline {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "catmull-rom" => Some(Spline::CatmullRom),
+            "bezier" => Some(Spline::Bezier),
+            _ => None,
+        }
+    }
+}
+
+// Remaps time over the whole path, so the camera can ease out of the first keyframe and into the
+// last.
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease-in" => Some(Easing::EaseIn),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, x: f64) -> f64 {
+        match self {
+            Easing::Linear => x,
+            Easing::EaseIn => x * x,
+            Easing::EaseOut => x * (2.0 - x),
+            Easing::EaseInOut => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    spline: Spline,
+    easing: Easing,
+}
+
+impl CameraPath {
+    pub fn new(keyframes: Vec<Keyframe>, spline: Spline) -> Self {
+        assert!(!keyframes.is_empty(), "A camera path needs at least one keyframe");
+        if let Spline::Bezier = spline {
+            assert!(keyframes.len() % 3 == 1, "A Bezier camera path needs 3n + 1 keyframes");
+        }
+
+        CameraPath {
+            keyframes,
+            spline,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_spline(self, spline: Spline) -> Self {
+        CameraPath::new(self.keyframes, spline).with_easing(self.easing)
+    }
+
+    pub fn with_easing(self, easing: Easing) -> Self {
+        CameraPath { easing, ..self }
+    }
+
+    // The camera pose at `time`, held at the first and last keyframes outside the path.
+    pub fn evaluate(&self, time: f64) -> Keyframe {
+        let step = match self.spline {
+            Spline::CatmullRom => 1,
+            Spline::Bezier => 3,
+        };
+        let keys = &self.keyframes;
+        let (start, end) = (keys[0].time, keys[keys.len() - 1].time);
+        if keys.len() == 1 || end <= start {
+            return Keyframe { time, ..keys[0] };
+        }
+
+        let x = (time - start) / (end - start);
+        let time = start + self.easing.apply(clamp(x, 0.0, 1.0)) * (end - start);
+
+        // Find the segment holding `time`, and how far along it the time is.
+        let mut k = 0;
+        while k + step < keys.len() - 1 && keys[k + step].time <= time {
+            k += step;
+        }
+        let span = keys[k + step].time - keys[k].time;
+        let u = if span > 0.0 { (time - keys[k].time) / span } else { 0.0 };
+
+        // The end keyframes are repeated to give Catmull-Rom its outer control points, as if a
+        // segment as long as the end one had passed.
+        let (t1, t2) = (keys[k].time, keys[k + step].time);
+        let t0 = if k > 0 { keys[k - 1].time } else { 2.0 * t1 - t2 };
+        let t3 = if k + 2 < keys.len() { keys[k + 2].time } else { 2.0 * t2 - t1 };
+        let interpolate = |f: &dyn Fn(&Keyframe) -> Vec3| match self.spline {
+            Spline::CatmullRom => {
+                let p0 = f(&keys[k.saturating_sub(1)]);
+                let p3 = f(&keys[(k + 2).min(keys.len() - 1)]);
+                catmull_rom([p0, f(&keys[k]), f(&keys[k + 1]), p3], [t0, t1, t2, t3], u)
+            }
+            Spline::Bezier => bezier(f(&keys[k]), f(&keys[k + 1]), f(&keys[k + 2]), f(&keys[k + 3]), u),
+        };
+
+        // The field of view and focus distance ride along in one vector.
+        let lenses = interpolate(&|key| Vec3::new(key.vfov, key.focus_dist, 0.0));
+        Keyframe {
+            time,
+            lookfrom: interpolate(&|key| key.lookfrom),
+            lookat: interpolate(&|key| key.lookat),
+            vup: Vec3::unit_vector(interpolate(&|key| key.vup)),
+            vfov: lenses.x(),
+            focus_dist: lenses.y(),
+        }
+    }
+}
+
+// The keyframes can be unevenly spaced in time, so the tangents are velocities over the
+// neighbouring keyframes, scaled to the length of the segment.
+fn catmull_rom(p: [Vec3; 4], t: [f64; 4], u: f64) -> Vec3 {
+    let span = t[2] - t[1];
+    let tangent = |a: usize, b: usize| if t[b] > t[a] { (p[b] - p[a]) * (span / (t[b] - t[a])) } else { Vec3::new(0.0, 0.0, 0.0) };
+    let (m1, m2) = (tangent(0, 2), tangent(1, 3));
+    bezier(p[1], p[1] + m1 * (1.0 / 3.0), p[2] - m2 * (1.0 / 3.0), p[2], u)
+}
+
+fn bezier(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, u: f64) -> Vec3 {
+    let v = 1.0 - u;
+    p0 * (v * v * v) + p1 * (3.0 * v * v * u) + p2 * (3.0 * v * u * u) + p3 * (u * u * u)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframes() -> Vec<Keyframe> {
+        let lookat = Point3::new(0.0, 0.0, 0.0);
+        vec![
+            Keyframe::new(0.0, Point3::new(10.0, 0.0, 0.0), lookat, 40.0),
+            Keyframe::new(2.0, Point3::new(0.0, 5.0, 10.0), lookat, 30.0),
+            Keyframe::new(3.0, Point3::new(-10.0, 0.0, 0.0), lookat, 50.0),
+            Keyframe::new(9.0, Point3::new(0.0, 0.0, -10.0), lookat, 40.0).with_vup(Vec3::new(1.0, 1.0, 0.0)),
+        ]
+    }
+
+    #[test]
+    fn path_passes_through_each_keyframe() {
+        for spline in [Spline::CatmullRom, Spline::Bezier].iter() {
+            let path = CameraPath::new(keyframes(), *spline);
+            let step = if let Spline::Bezier = spline { 3 } else { 1 };
+            for key in keyframes().iter().step_by(step) {
+                let pose = path.evaluate(key.time);
+                assert!((pose.lookfrom - key.lookfrom).length() < 1e-9);
+                assert!((pose.vup - Vec3::unit_vector(key.vup)).length() < 1e-9);
+                assert!(f64::abs(pose.vfov - key.vfov) < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn catmull_rom_tangents_follow_keyframe_times() {
+        // Evenly spaced points on a line, visited at a steady speed but with uneven keyframe times,
+        // are passed through at that steady speed.
+        let keys = (0..4)
+            .map(|i| {
+                let time = [0.0, 1.0, 4.0, 5.0][i];
+                Keyframe::new(time, Point3::new(time, 0.0, 0.0), Point3::new(time, 0.0, -1.0), 40.0)
+            })
+            .collect();
+        let path = CameraPath::new(keys, Spline::CatmullRom);
+        for &time in [1.5, 2.5, 3.5].iter() {
+            assert!(f64::abs(path.evaluate(time).lookfrom.x() - time) < 1e-9);
+        }
+    }
+}
@@ -14,18 +14,132 @@ pub fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T {
     }
 }
 
-pub fn write_color(f: &mut impl Write, color: Color, samples_per_pixel: u32) -> std::io::Result<()> {
-    let scale = 1.0 / samples_per_pixel as f64;
+pub fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        f64::powf((v + 0.055) / 1.055, 2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * f64::powf(v, 1.0 / 2.4) - 0.055
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ColorEncoding {
+    Srgb,
+    Linear,
+}
 
-    let r = f64::sqrt(color.x() * scale);
-    let g = f64::sqrt(color.y() * scale);
-    let b = f64::sqrt(color.z() * scale);
+impl ColorEncoding {
+    pub fn decode(&self, v: f64) -> f64 {
+        match self {
+            ColorEncoding::Srgb => srgb_to_linear(v),
+            ColorEncoding::Linear => v,
+        }
+    }
+}
+
+const LINEAR_SRGB_TO_ACESCG: [[f64; 3]; 3] = [
+    [0.6130974024, 0.3395231462, 0.0473794514],
+    [0.0701937225, 0.9163538791, 0.0134523985],
+    [0.0206155929, 0.1095697729, 0.8698146342],
+];
+
+// The exact inverse of the matrix above, so that colours survive the round trip.
+const ACESCG_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [1.7050509927, -0.6217921207, -0.0832588720],
+    [-0.1302564176, 1.1408047365, -0.0105483191],
+    [-0.0240033568, -0.1289689760, 1.1529723328],
+];
+
+pub(crate) fn mul(m: &[[f64; 3]; 3], c: &Color) -> Color {
+    Color::new(
+        m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+        m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+        m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z(),
+    )
+}
+
+// Scene colours and textures are authored in linear sRGB; the working space is what they are
+// converted to while rendering.
+#[derive(Clone, Copy)]
+pub enum ColorSpace {
+    LinearSrgb,
+    AcesCg,
+}
 
-    write!(
+impl ColorSpace {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear-srgb" => Some(ColorSpace::LinearSrgb),
+            "acescg" => Some(ColorSpace::AcesCg),
+            _ => None,
+        }
+    }
+
+    pub fn linear_srgb_to_working(&self, c: &Color) -> Color {
+        match self {
+            ColorSpace::LinearSrgb => *c,
+            ColorSpace::AcesCg => mul(&LINEAR_SRGB_TO_ACESCG, c),
+        }
+    }
+
+    pub fn working_to_linear_srgb(&self, c: &Color) -> Color {
+        match self {
+            ColorSpace::LinearSrgb => *c,
+            ColorSpace::AcesCg => mul(&ACESCG_TO_LINEAR_SRGB, c),
+        }
+    }
+}
+
+// Expects display-referred linear sRGB in [0, 1].
+pub fn write_color(f: &mut impl Write, color: Color) -> std::io::Result<()> {
+    let r = linear_to_srgb(clamp(color.x(), 0.0, 1.0));
+    let g = linear_to_srgb(clamp(color.y(), 0.0, 1.0));
+    let b = linear_to_srgb(clamp(color.z(), 0.0, 1.0));
+
+    writeln!(
         f,
-        "{} {} {}\n",
+        "{} {} {}",
         (256.0 * clamp(r, 0.0, 0.999)) as u32,
         (256.0 * clamp(g, 0.0, 0.999)) as u32,
         (256.0 * clamp(b, 0.0, 0.999)) as u32,
     )
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        for i in 0..=100 {
+            let v = i as f64 / 100.0;
+            assert!(f64::abs(srgb_to_linear(linear_to_srgb(v)) - v) < 1e-12);
+            assert!(f64::abs(linear_to_srgb(srgb_to_linear(v)) - v) < 1e-12);
+        }
+
+        // The linear segment and the curve meet, so the encoding is continuous.
+        assert!(f64::abs(srgb_to_linear(0.04045) - 0.04045 / 12.92) < 1e-9);
+        assert!(f64::abs(linear_to_srgb(0.0031308 + 1e-12) - linear_to_srgb(0.0031308)) < 1e-6);
+    }
+
+    #[test]
+    fn acescg_round_trips_to_linear_srgb() {
+        let colors = [Color::new(1.0, 1.0, 1.0), Color::new(0.8, 0.2, 0.05), Color::new(0.0, 0.3, 10.0)];
+        for c in colors.iter() {
+            let working = ColorSpace::AcesCg.linear_srgb_to_working(c);
+            assert!((ColorSpace::AcesCg.working_to_linear_srgb(&working) - *c).length() < 1e-6 * f64::max(1.0, c.length()));
+        }
+
+        // Both spaces share the D65-adapted white.
+        let white = ColorSpace::AcesCg.linear_srgb_to_working(&Color::new(1.0, 1.0, 1.0));
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 1e-6);
+    }
+}
@@ -1,74 +1,188 @@
 use std::rc::Rc;
 use crate::hittable::{Hittable, HitRecord};
-use crate::material::{Material, Isotropic};
-use crate::texture::Texture;
+use crate::material::{Material, MediumBoundary, Volume};
+use crate::medium::Medium;
+use crate::phase::PhaseFunction;
+use crate::texture::{SolidColor, Texture};
+use crate::color::Color;
 use crate::ray::Ray;
 use crate::aabb::Aabb;
 use crate::random::random;
 use crate::vec3::Vec3;
 
+// Distance to the next collision in a medium with extinction coefficient `sigma_t`, for `u` uniform
+// in [0, 1).
+pub fn sample_free_flight(sigma_t: f64, u: f64) -> f64 {
+    -f64::ln(1.0 - u) / sigma_t
+}
+
+// Walks the crossings of `boundary` along the ray and calls `f` with each span [t0, t1] that lies
+// inside it, clipped to [t_min, t_max], until `f` reports a collision. A crossing through the back of
+// a face ends a span, so this handles non-convex boundaries and rays that start inside.
+pub fn hit_inside<F: FnMut(f64, f64) -> bool>(boundary: &dyn Hittable, r: &Ray, t_min: f64, t_max: f64, mut f: F) -> bool {
+    let mut t_start = t_min;
+    let mut t_search = t_min;
+    while t_start < t_max {
+        let mut crossing = HitRecord::new();
+        if !boundary.hit(r, t_search, f64::INFINITY, &mut crossing) {
+            return false;
+        }
+
+        if !crossing.front_face && f(t_start, f64::min(crossing.t, t_max)) {
+            return true;
+        }
+
+        t_start = crossing.t;
+        t_search = crossing.t + 0.0001;
+    }
+
+    false
+}
+
+// A homogeneous medium filling `boundary`. Media with coloured extinction are traced by the path's
+// medium stack like any other `Medium`, so they need the camera's medium set to enclose the camera.
 pub struct ConstantMedium {
     boundary: Rc<dyn Hittable>,
     phase_function: Rc<dyn Material>,
-    neg_inv_density: f64,
+    albedo: Rc<dyn Texture>,
+    density: f64,
+    sigma: Option<(Color, Color)>,
 }
 
 impl ConstantMedium {
     pub fn new(b: Rc<dyn Hittable>, d: f64, a: Rc<dyn Texture>) -> Self {
         ConstantMedium {
             boundary: b,
-            neg_inv_density: -1.0 / d,
-            phase_function: Rc::new(Isotropic::new(a)),
+            phase_function: Rc::new(Volume::new(a.clone(), PhaseFunction::Isotropic)),
+            albedo: a,
+            density: d,
+            sigma: None,
         }
     }
-}
 
-impl Hittable for ConstantMedium {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut rec1 = HitRecord::new();
-        if ! self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY, &mut rec1) {
-            return false;
-        }
-
-        let mut rec2 = HitRecord::new();
-        if ! self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY, &mut rec2) {
-            return false;
+    // `sigma_a` and `sigma_s` are the absorption and scattering coefficients per colour channel.
+    pub fn new_colored(b: Rc<dyn Hittable>, sigma_a: Color, sigma_s: Color) -> Self {
+        ConstantMedium {
+            phase_function: Rc::new(MediumBoundary::new(Rc::new(Medium::new(sigma_a, sigma_s)))),
+            sigma: Some((sigma_a, sigma_s)),
+            ..ConstantMedium::new(b, 0.0, Rc::new(SolidColor::new_scalar(1.0)))
         }
+    }
 
-        if rec1.t < t_min {
-            rec1.t = t_min;
-        };
-        if rec2.t > t_max {
-            rec2.t = t_max;
+    pub fn with_phase(self, phase: PhaseFunction) -> Self {
+        let phase_function: Rc<dyn Material> = match self.sigma {
+            Some((sigma_a, sigma_s)) => Rc::new(MediumBoundary::new(Rc::new(Medium::new(sigma_a, sigma_s).with_phase(phase)))),
+            None => Rc::new(Volume::new(self.albedo.clone(), phase)),
         };
 
-        if rec1.t >= rec2.t {
-            return false;
+        ConstantMedium {
+            phase_function,
+            ..self
         }
+    }
+}
 
-        if rec1.t < 0.0 {
-            rec1.t = 0.0;
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Coloured media only report their boundary, which the path crosses into or out of.
+        if self.sigma.is_some() {
+            if !self.boundary.hit(r, t_min, t_max, rec) {
+                return false;
+            }
+
+            rec.mat_ptr = Option::Some(self.phase_function.clone());
+            return true;
         }
 
         let ray_length = r.direction().length();
-        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = self.neg_inv_density * random().log2();
 
-        if hit_distance > distance_inside_boundary {
-            return false;
-        }
+        hit_inside(self.boundary.as_ref(), r, t_min, t_max, |t0, t1| {
+            let t = t0 + sample_free_flight(self.density * ray_length, random());
+            if t >= t1 {
+                return false;
+            }
 
-        rec.t = rec1.t + hit_distance / ray_length;
-        rec.p =  r.at(rec.t);
+            rec.t = t;
+            rec.p = r.at(t);
 
-        rec.normal = Vec3::new(1.0, 0.0, 0.0);
-        rec.front_face = true;
-        rec.mat_ptr = Option::Some(self.phase_function.clone());
+            rec.normal = Vec3::new(1.0, 0.0, 0.0);
+            rec.geometric_normal = rec.normal;
+            rec.front_face = true;
+            rec.mat_ptr = Option::Some(self.phase_function.clone());
 
-        true
+            true
+        })
     }
 
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
         self.boundary.bounding_box(time0, time1, output_box)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::point3::Point3;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn free_flight_inverts_the_exponential_cdf() {
+        let sigma_t = 0.5;
+        for &x in [0.1, 1.0, 2.0, 10.0].iter() {
+            let u = 1.0 - f64::exp(-sigma_t * x);
+            assert!(f64::abs(sample_free_flight(sigma_t, u) - x) < 1e-9);
+        }
+    }
+
+    fn spans(boundary: &dyn Hittable, r: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let mut spans = Vec::new();
+        hit_inside(boundary, r, t_min, t_max, |t0, t1| {
+            spans.push((t0, t1));
+            false
+        });
+        spans
+    }
+
+    fn sphere(x: f64, radius: f64) -> Rc<dyn Hittable> {
+        Rc::new(Sphere::new(Point3::new(x, 0.0, 0.0), radius, Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    #[test]
+    fn hit_inside_visits_each_part_of_a_non_convex_boundary() {
+        let mut boundary = HittableList::new();
+        boundary.add(sphere(2.0, 1.0));
+        boundary.add(sphere(6.0, 1.0));
+        let r = Ray::new(Point3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+        let inside = spans(&boundary, &r, 0.001, f64::INFINITY);
+        assert_eq!(inside.len(), 2);
+        assert!(f64::abs(inside[0].0 - 3.0) < 1e-9 && f64::abs(inside[0].1 - 5.0) < 1e-9);
+        assert!(f64::abs(inside[1].0 - 7.0) < 1e-9 && f64::abs(inside[1].1 - 9.0) < 1e-9);
+
+        // The gap between the parts is never inside, however the ray is clipped.
+        let inside = spans(&boundary, &r, 4.0, 8.0);
+        assert_eq!(inside.len(), 2);
+        assert!(f64::abs(inside[0].0 - 4.0) < 1e-9 && f64::abs(inside[0].1 - 5.0) < 1e-9);
+        assert!(f64::abs(inside[1].0 - 7.0) < 1e-9 && f64::abs(inside[1].1 - 8.0) < 1e-9);
+    }
+
+    #[test]
+    fn hit_inside_starts_at_the_origin_of_a_ray_inside_a_sphere() {
+        let boundary = sphere(0.0, 2.0);
+        let r = Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+
+        let inside = spans(boundary.as_ref(), &r, 0.001, f64::INFINITY);
+        assert_eq!(inside.len(), 1);
+        assert!(f64::abs(inside[0].0 - 0.001) < 1e-9 && f64::abs(inside[0].1 - 0.75) < 1e-9);
+    }
+
+    #[test]
+    fn free_flight_mean_is_mean_free_path() {
+        let sigma_t = 4.0;
+        let n = 100000;
+        let mean = (0..n).map(|i| sample_free_flight(sigma_t, (i as f64 + 0.5) / n as f64)).sum::<f64>() / n as f64;
+        assert!(f64::abs(mean - 1.0 / sigma_t) < 1e-3);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::color::Color;
+use crate::film::Film;
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+const ITERATIONS: u32 = 5;
+const COLOR_SIGMA: f64 = 0.5;
+const ALBEDO_SIGMA: f64 = 0.1;
+const NORMAL_POWER: i32 = 64;
+
+// Compresses highlights so single fireflies don't dominate the colour edge-stopping weight.
+fn compress(c: &Color) -> Color {
+    Color::new(c.x() / (1.0 + c.x()), c.y() / (1.0 + c.y()), c.z() / (1.0 + c.z()))
+}
+
+fn divide(a: &Color, b: &Color) -> Color {
+    let eps = 1e-3;
+    Color::new(a.x() / (b.x() + eps), a.y() / (b.y() + eps), a.z() / (b.z() + eps))
+}
+
+fn multiply(a: &Color, b: &Color) -> Color {
+    let eps = 1e-3;
+    Color::new(a.x() * (b.x() + eps), a.y() * (b.y() + eps), a.z() * (b.z() + eps))
+}
+
+fn a_trous_step(illumination: &Film, albedo: &Film, normal: &Film, step: i64, color_sigma: f64) -> Film {
+    let width = illumination.width as i64;
+    let height = illumination.height as i64;
+    let mut filtered = Film::new(illumination.width, illumination.height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let c_p = illumination.get(x as u32, y as u32);
+            let a_p = albedo.get(x as u32, y as u32);
+            let n_p = normal.get(x as u32, y as u32);
+
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut weight_sum = 0.0;
+            for (j, kj) in KERNEL.iter().enumerate() {
+                for (i, ki) in KERNEL.iter().enumerate() {
+                    let qx = x + (i as i64 - 2) * step;
+                    let qy = y + (j as i64 - 2) * step;
+                    if qx < 0 || qx >= width || qy < 0 || qy >= height {
+                        continue;
+                    }
+
+                    let c_q = illumination.get(qx as u32, qy as u32);
+                    let a_q = albedo.get(qx as u32, qy as u32);
+                    let n_q = normal.get(qx as u32, qy as u32);
+
+                    let w_c = f64::exp(-(compress(&c_p) - compress(&c_q)).length_squared() / (color_sigma * color_sigma));
+                    let w_a = f64::exp(-(a_p - a_q).length_squared() / (ALBEDO_SIGMA * ALBEDO_SIGMA));
+                    let w_n = f64::powi(f64::max(0.0, Color::dot(&n_p, &n_q)), NORMAL_POWER);
+
+                    let weight = ki * kj * w_c * w_a * w_n;
+                    sum += weight * c_q;
+                    weight_sum += weight;
+                }
+            }
+
+            filtered.set(x as u32, y as u32, if weight_sum > 0.0 {
+                sum / weight_sum
+            } else {
+                c_p
+            });
+        }
+    }
+
+    filtered
+}
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Lighting is separated from the
+// albedo before filtering so texture detail survives, then recombined afterwards.
+pub fn denoise(color: &Film, albedo: &Film, normal: &Film) -> Film {
+    let mut illumination = Film::new(color.width, color.height);
+    for y in 0..color.height {
+        for x in 0..color.width {
+            illumination.set(x, y, divide(&color.get(x, y), &albedo.get(x, y)));
+        }
+    }
+
+    for i in 0..ITERATIONS {
+        let color_sigma = COLOR_SIGMA / f64::powi(2.0, i as i32);
+        illumination = a_trous_step(&illumination, albedo, normal, 1 << i, color_sigma);
+    }
+
+    let mut denoised = Film::new(color.width, color.height);
+    for y in 0..color.height {
+        for x in 0..color.width {
+            denoised.set(x, y, multiply(&illumination.get(x, y), &albedo.get(x, y)));
+        }
+    }
+
+    denoised
+}
@@ -0,0 +1,192 @@
+use std::io::Write;
+
+use crate::color::{Color, ColorSpace, write_color};
+use crate::filter::PixelFilter;
+use crate::tonemap::ToneMapping;
+
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize],
+        }
+    }
+
+    // Rows are counted from the top of the image.
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn add(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] += color;
+    }
+
+    // Writes the raw values as a little-endian PFM, for passes that must not be tone mapped.
+    pub fn write_pfm(&self, f: &mut impl Write) -> std::io::Result<()> {
+        write!(f, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width as usize).rev() {
+            for pixel in row {
+                for c in pixel.e.iter() {
+                    f.write_all(&(*c as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_ppm(&self, f: &mut impl Write, tone_mapping: &ToneMapping, working_space: ColorSpace) -> std::io::Result<()> {
+        writeln!(f, "P3\n{} {}\n255", self.width, self.height)?;
+        for pixel in self.pixels.iter() {
+            let color = tone_mapping.apply(&working_space.working_to_linear_srgb(pixel));
+            write_color(f, color)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Accumulates samples through a reconstruction filter, adding each one to every pixel the filter
+// reaches. Positions are in pixels from the top left corner of the image.
+pub struct SplatFilm {
+    film: Film,
+    weights: Vec<f64>,
+    filter: PixelFilter,
+    tiles: (u32, u32),
+}
+
+impl SplatFilm {
+    pub fn new(width: u32, height: u32, filter: PixelFilter) -> Self {
+        SplatFilm {
+            film: Film::new(width, height),
+            weights: vec![0.0; (width * height) as usize],
+            filter,
+            tiles: (1, 1),
+        }
+    }
+
+    // Splits the image into `columns` by `rows` tiles that samples don't reach across, such as the
+    // two eyes of a stereo pair.
+    pub fn with_tiles(self, columns: u32, rows: u32) -> Self {
+        SplatFilm {
+            tiles: (columns, rows),
+            ..self
+        }
+    }
+
+    // Calls `f` with each pixel a sample at (`x`, `y`) reaches, and the filter's weight there.
+    pub fn splat(&self, x: f64, y: f64, f: impl FnMut(u32, u32, f64)) {
+        splat(&self.filter, self.tile(x, y), x, y, f);
+    }
+
+    pub fn add_sample(&mut self, x: f64, y: f64, color: &Color) {
+        let tile = self.tile(x, y);
+        let (film, weights) = (&mut self.film, &mut self.weights);
+        splat(&self.filter, tile, x, y, |px, py, weight| {
+            film.add(px, py, weight * *color);
+            weights[(py * film.width + px) as usize] += weight;
+        });
+    }
+
+    // The pixels of the tile holding (`x`, `y`), as [left, right) by [top, bottom).
+    fn tile(&self, x: f64, y: f64) -> (u32, u32, u32, u32) {
+        let (width, height) = (self.film.width, self.film.height);
+        let (columns, rows) = self.tiles;
+        let column = u32::min(x.max(0.0) as u32 * columns / width, columns - 1);
+        let row = u32::min(y.max(0.0) as u32 * rows / height, rows - 1);
+        (column * width / columns, (column + 1) * width / columns, row * height / rows, (row + 1) * height / rows)
+    }
+
+    // Divides each pixel of `film` by the total weight of the samples added here, for passes
+    // splatted alongside this film.
+    pub fn normalize(&self, film: &mut Film) {
+        for y in 0..film.height {
+            for x in 0..film.width {
+                let weight = self.weights[(y * film.width + x) as usize];
+                let color = if weight > 0.0 { film.get(x, y) / weight } else { Color::new(0.0, 0.0, 0.0) };
+                film.set(x, y, color);
+            }
+        }
+    }
+
+    pub fn resolve(mut self) -> Film {
+        let mut film = std::mem::replace(&mut self.film, Film::new(0, 0));
+        self.normalize(&mut film);
+        film
+    }
+}
+
+fn splat(filter: &PixelFilter, tile: (u32, u32, u32, u32), x: f64, y: f64, mut f: impl FnMut(u32, u32, f64)) {
+    let (left, right, top, bottom) = tile;
+    let r = filter.radius;
+    let x0 = f64::max(f64::ceil(x - 0.5 - r), left as f64);
+    let y0 = f64::max(f64::ceil(y - 0.5 - r), top as f64);
+    let x1 = f64::min(f64::floor(x - 0.5 + r), right as f64 - 1.0);
+    let y1 = f64::min(f64::floor(y - 0.5 + r), bottom as f64 - 1.0);
+
+    if x1 < x0 || y1 < y0 {
+        return;
+    }
+
+    for py in y0 as u32..=y1 as u32 {
+        for px in x0 as u32..=x1 as u32 {
+            let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+            if weight != 0.0 {
+                f(px, py, weight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_pixel_box_filter_averages_each_pixel() {
+        let mut film = SplatFilm::new(3, 2, PixelFilter::from_name("box", Some(0.5)).unwrap());
+        let mut sums = [Color::new(0.0, 0.0, 0.0); 6];
+        let offsets = [(0.0, 0.0), (0.25, 0.75), (0.5, 0.5), (0.999, 0.1)];
+        for py in 0..2 {
+            for px in 0..3 {
+                for (k, (dx, dy)) in offsets.iter().enumerate() {
+                    let color = Color::new((px + k) as f64, py as f64, k as f64);
+                    film.add_sample(px as f64 + dx, py as f64 + dy, &color);
+                    sums[py * 3 + px] += color;
+                }
+            }
+        }
+
+        let film = film.resolve();
+        for py in 0..2 {
+            for px in 0..3 {
+                let average = sums[py * 3 + px] / offsets.len() as f64;
+                assert!((film.get(px as u32, py as u32) - average).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_keep_splats_apart() {
+        let mut film = SplatFilm::new(4, 1, PixelFilter::from_name("gaussian", None).unwrap()).with_tiles(2, 1);
+        let mut reached = Vec::new();
+        film.splat(1.9, 0.5, |px, _, _| reached.push(px));
+        assert_eq!(reached, vec![0, 1]);
+
+        film.add_sample(2.1, 0.5, &Color::new(1.0, 1.0, 1.0));
+        let film = film.resolve();
+        assert_eq!(film.get(1, 0).x(), 0.0);
+        assert_eq!(film.get(2, 0).x(), 1.0);
+    }
+}
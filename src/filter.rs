@@ -0,0 +1,119 @@
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Box,
+    Gaussian,
+    MitchellNetravali,
+    BlackmanHarris,
+    Lanczos,
+}
+
+// A separable reconstruction filter reaching `radius` pixels from each sample. A box of radius 0.5
+// gives each sample to the pixel it falls in, as a plain average does.
+#[derive(Clone, Copy)]
+pub struct PixelFilter {
+    filter: Filter,
+    pub radius: f64,
+}
+
+impl PixelFilter {
+    pub fn from_name(name: &str, radius: Option<f64>) -> Option<Self> {
+        let (filter, default_radius) = match name {
+            "box" => (Filter::Box, 0.5),
+            "gaussian" => (Filter::Gaussian, 1.5),
+            "mitchell" => (Filter::MitchellNetravali, 2.0),
+            "blackman-harris" => (Filter::BlackmanHarris, 2.0),
+            "lanczos" => (Filter::Lanczos, 3.0),
+            _ => return None,
+        };
+
+        Some(PixelFilter {
+            filter,
+            radius: radius.unwrap_or(default_radius),
+        })
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        // The box is half open, so a sample on the edge between two pixels goes to just one of them.
+        if let Filter::Box = self.filter {
+            return if -self.radius < x && x <= self.radius { 1.0 } else { 0.0 };
+        }
+
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+
+        match self.filter {
+            Filter::Box => 1.0,
+            Filter::Gaussian => {
+                // Shifted down so that it reaches zero at the radius.
+                let gaussian = |x: f64| f64::exp(-2.0 * x * x);
+                f64::max(gaussian(x) - gaussian(self.radius), 0.0)
+            }
+            Filter::MitchellNetravali => mitchell_netravali(2.0 * x / self.radius, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::BlackmanHarris => {
+                // Shifted down by its small value at the ends of the window, to reach zero there.
+                let window = |n: f64| {
+                    0.35875 - 0.48829 * f64::cos(2.0 * PI * n) + 0.14128 * f64::cos(4.0 * PI * n)
+                        - 0.01168 * f64::cos(6.0 * PI * n)
+                };
+                window(0.5 + 0.5 * x / self.radius) - window(1.0)
+            }
+            Filter::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+// The cubic from Mitchell and Netravali (1988), over [0, 2].
+fn mitchell_netravali(x: f64, b: f64, c: f64) -> f64 {
+    let (x2, x3) = (x * x, x * x * x);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+    };
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        f64::sin(PI * x) / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mitchell_netravali_peak_is_8_over_9() {
+        let filter = PixelFilter::from_name("mitchell", None).unwrap();
+        assert!(f64::abs(filter.evaluate(0.0, 0.0) - 64.0 / 81.0) < 1e-12);
+        assert!(f64::abs(filter.evaluate_1d(0.0) - 8.0 / 9.0) < 1e-12);
+    }
+
+    #[test]
+    fn filters_reach_zero_at_their_radius() {
+        for name in ["gaussian", "mitchell", "blackman-harris", "lanczos"].iter() {
+            for &radius in [None, Some(1.0), Some(2.5)].iter() {
+                let filter = PixelFilter::from_name(name, radius).unwrap();
+                assert!(f64::abs(filter.evaluate_1d(filter.radius)) < 1e-12, "{}", name);
+                assert!(f64::abs(filter.evaluate_1d(-filter.radius)) < 1e-12, "{}", name);
+                assert!(filter.evaluate_1d(0.0) > 0.0, "{}", name);
+            }
+        }
+
+        // The box is open on one side, so its edge belongs to one pixel.
+        let filter = PixelFilter::from_name("box", None).unwrap();
+        assert_eq!(filter.evaluate_1d(-0.5), 0.0);
+        assert_eq!(filter.evaluate_1d(0.5), 1.0);
+    }
+}
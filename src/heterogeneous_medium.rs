@@ -0,0 +1,79 @@
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::color::clamp;
+use crate::constant_medium::{hit_inside, sample_free_flight};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Material, Volume};
+use crate::phase::PhaseFunction;
+use crate::point3::Point3;
+use crate::random::random;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+// A medium whose density varies over space. The density texture is read as a fraction of
+// `max_density` (clamped to [0, 1]), so `max_density` is the majorant for delta tracking.
+pub struct HeterogeneousMedium {
+    boundary: Rc<dyn Hittable>,
+    phase_function: Rc<dyn Material>,
+    albedo: Rc<dyn Texture>,
+    density: Rc<dyn Texture>,
+    max_density: f64,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(b: Rc<dyn Hittable>, density: Rc<dyn Texture>, max_density: f64, a: Rc<dyn Texture>) -> Self {
+        HeterogeneousMedium {
+            boundary: b,
+            phase_function: Rc::new(Volume::new(a.clone(), PhaseFunction::Isotropic)),
+            albedo: a,
+            density,
+            max_density,
+        }
+    }
+
+    pub fn with_phase(mut self, phase: PhaseFunction) -> Self {
+        self.phase_function = Rc::new(Volume::new(self.albedo.clone(), phase));
+        self
+    }
+
+    fn density_fraction(&self, p: &Point3) -> f64 {
+        clamp(self.density.scalar_value(0.0, 0.0, p), 0.0, 1.0)
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let ray_length = r.direction().length();
+
+        hit_inside(self.boundary.as_ref(), r, t_min, t_max, |t0, t1| {
+            // Delta tracking: take exponential steps against the majorant and accept a collision with
+            // probability density / majorant, otherwise treat it as a null collision and keep going.
+            let mut t = t0;
+            loop {
+                t += sample_free_flight(self.max_density * ray_length, random());
+                if t >= t1 {
+                    return false;
+                }
+
+                let p = r.at(t);
+                if random() < self.density_fraction(&p) {
+                    rec.t = t;
+                    rec.p = p;
+
+                    rec.normal = Vec3::new(1.0, 0.0, 0.0);
+                    rec.geometric_normal = rec.normal;
+                    rec.front_face = true;
+                    rec.mat_ptr = Option::Some(self.phase_function.clone());
+
+                    return true;
+                }
+            }
+        })
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(time0, time1, output_box)
+    }
+}
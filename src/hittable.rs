@@ -6,14 +6,20 @@ use crate::point3::Point3;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub mat_ptr: Option<Rc<dyn Material>>,
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    pub footprint: f64,
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -21,11 +27,16 @@ impl HitRecord {
         HitRecord {
             p: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
             normal: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            geometric_normal: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
             mat_ptr: None,
             t: f64::NEG_INFINITY,
             u: 0.0,
             v: 0.0,
             front_face: false,
+            footprint: 0.0,
+            object_id: 0,
         }
     }
 
@@ -35,7 +46,30 @@ impl HitRecord {
             *outward_normal
         } else {
             -outward_normal
-        }
+        };
+        self.geometric_normal = self.normal;
+    }
+
+    // Size of the ray footprint in texture space along u and v.
+    pub fn uv_footprint(&self) -> (f64, f64) {
+        let du = if self.tangent.length_squared() > 0.0 {
+            self.footprint / self.tangent.length()
+        } else {
+            0.0
+        };
+        let dv = if self.bitangent.length_squared() > 0.0 {
+            self.footprint / self.bitangent.length()
+        } else {
+            0.0
+        };
+
+        (du, dv)
+    }
+
+    // `tangent` and `bitangent` are the surface derivatives dp/du and dp/dv.
+    pub fn set_tangents(&mut self, dpdu: Vec3, dpdv: Vec3) {
+        self.tangent = dpdu;
+        self.bitangent = dpdv;
     }
 }
 
@@ -45,6 +79,64 @@ pub trait Hittable {
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool;
 }
 
+pub struct ObjectId {
+    ptr: Rc<dyn Hittable>,
+    id: u32,
+}
+
+impl ObjectId {
+    pub fn new(p: Rc<dyn Hittable>, id: u32) -> Self {
+        ObjectId {
+            ptr: p,
+            id,
+        }
+    }
+}
+
+impl Hittable for ObjectId {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.ptr.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.object_id = self.id;
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.ptr.bounding_box(time0, time1, output_box)
+    }
+}
+
+// Swaps which side of the wrapped object counts as the outside.
+pub struct FlipFace {
+    ptr: Rc<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(p: Rc<dyn Hittable>) -> Self {
+        FlipFace {
+            ptr: p,
+        }
+    }
+}
+
+impl Hittable for FlipFace {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.ptr.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.front_face = !rec.front_face;
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.ptr.bounding_box(time0, time1, output_box)
+    }
+}
+
 pub struct Translate {
     ptr: Rc<dyn Hittable>,
     offset: Vec3,
@@ -67,8 +159,8 @@ impl Hittable for Translate {
         }
 
         rec.p += self.offset;
-        let normal = rec.normal;
-        rec.set_face_normal(&moved_r, &normal);
+        let outward_normal = if rec.front_face { rec.normal } else { -rec.normal };
+        rec.set_face_normal(r, &outward_normal);
 
         true
     }
@@ -144,6 +236,14 @@ impl RotateY {
             bbox: Aabb::new(min, max),
         }
     }
+
+    fn rotate_out(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -172,14 +272,13 @@ impl Hittable for RotateY {
             -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2],
         );
 
-        let normal = Vec3::new(
-            self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2],
-            rec.normal[1],
-            -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2],
-        );
+        let outward_normal = self.rotate_out(&if rec.front_face { rec.normal } else { -rec.normal });
+        let tangent = self.rotate_out(&rec.tangent);
+        let bitangent = self.rotate_out(&rec.bitangent);
 
         rec.p = p;
-        rec.set_face_normal(&rotated_r, &normal);
+        rec.set_face_normal(r, &outward_normal);
+        rec.set_tangents(tangent, bitangent);
 
         true
     }
@@ -1,7 +1,7 @@
 use std::rc::Rc;
 
 use crate::aabb::Aabb;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{HitRecord, Hittable, ObjectId};
 use crate::point3::Point3;
 use crate::ray::Ray;
 
@@ -19,6 +19,16 @@ impl HittableList {
     pub fn add(&mut self, object: Rc<dyn Hittable>) {
         self.objects.push(object)
     }
+
+    // Numbers the top-level objects from 1 for the object id pass; 0 means nothing was hit.
+    pub fn with_object_ids(self) -> HittableList {
+        let mut list = HittableList::new();
+        for (i, object) in self.objects.into_iter().enumerate() {
+            list.add(Rc::new(ObjectId::new(object, i as u32 + 1)));
+        }
+
+        list
+    }
 }
 
 impl Hittable for HittableList {
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::color::Color;
+use crate::film::{Film, SplatFilm};
+use crate::material::Lobe;
+
+const LOBES: [Lobe; 4] = [Lobe::Diffuse, Lobe::Specular, Lobe::Transmission, Lobe::Volume];
+
+fn lobe_name(lobe: Lobe) -> &'static str {
+    match lobe {
+        Lobe::Diffuse => "diffuse",
+        Lobe::Specular => "specular",
+        Lobe::Transmission => "transmission",
+        Lobe::Volume => "volume",
+    }
+}
+
+// Radiance carried by one camera path, split by how it reached the camera.
+pub struct PathRadiance {
+    pub total: Color,
+    pub emission: Color,
+    pub direct: [Color; 4],
+    pub indirect: [Color; 4],
+    pub light_groups: Vec<(String, Color)>,
+}
+
+impl PathRadiance {
+    pub fn new() -> Self {
+        PathRadiance {
+            total: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            direct: [Color::new(0.0, 0.0, 0.0); 4],
+            indirect: [Color::new(0.0, 0.0, 0.0); 4],
+            light_groups: Vec::new(),
+        }
+    }
+
+    // `bounce` is the number of scattering events before the emitter was reached, and `first_lobe`
+    // the lobe sampled at the first of them.
+    pub fn add(&mut self, radiance: Color, bounce: u32, first_lobe: Option<Lobe>, light_group: &str) {
+        if radiance.length_squared() == 0.0 {
+            return;
+        }
+
+        self.total += radiance;
+        match first_lobe {
+            None => self.emission += radiance,
+            Some(lobe) if bounce == 1 => self.direct[lobe as usize] += radiance,
+            Some(lobe) => self.indirect[lobe as usize] += radiance,
+        }
+
+        match self.light_groups.iter_mut().find(|(name, _)| name == light_group) {
+            Some((_, group)) => *group += radiance,
+            None => self.light_groups.push((light_group.to_string(), radiance)),
+        }
+    }
+}
+
+pub struct LightPasses {
+    width: u32,
+    height: u32,
+    emission: Film,
+    direct: Vec<Film>,
+    indirect: Vec<Film>,
+    light_groups: Vec<(String, Film)>,
+}
+
+impl LightPasses {
+    pub fn new(width: u32, height: u32) -> Self {
+        LightPasses {
+            width,
+            height,
+            emission: Film::new(width, height),
+            direct: LOBES.iter().map(|_| Film::new(width, height)).collect(),
+            indirect: LOBES.iter().map(|_| Film::new(width, height)).collect(),
+            light_groups: Vec::new(),
+        }
+    }
+
+    // Splats the sample's passes the way `film` splats its total, so they resolve alike.
+    pub fn add_sample(&mut self, film: &SplatFilm, x: f64, y: f64, radiance: &PathRadiance) {
+        let mut groups = Vec::with_capacity(radiance.light_groups.len());
+        for (name, color) in radiance.light_groups.iter() {
+            let index = match self.light_groups.iter().position(|(group, _)| group == name) {
+                Some(index) => index,
+                None => {
+                    self.light_groups.push((name.clone(), Film::new(self.width, self.height)));
+                    self.light_groups.len() - 1
+                }
+            };
+            groups.push((index, *color));
+        }
+
+        film.splat(x, y, |px, py, weight| {
+            self.emission.add(px, py, weight * radiance.emission);
+            for lobe in LOBES.iter() {
+                self.direct[*lobe as usize].add(px, py, weight * radiance.direct[*lobe as usize]);
+                self.indirect[*lobe as usize].add(px, py, weight * radiance.indirect[*lobe as usize]);
+            }
+            for (index, color) in groups.iter() {
+                self.light_groups[*index].1.add(px, py, weight * *color);
+            }
+        });
+    }
+
+    // Divides the passes by the weights of the samples splatted into `film`.
+    pub fn resolve(mut self, film: &SplatFilm) -> Self {
+        film.normalize(&mut self.emission);
+        for pass in self.direct.iter_mut().chain(self.indirect.iter_mut()) {
+            film.normalize(pass);
+        }
+        for (_, pass) in self.light_groups.iter_mut() {
+            film.normalize(pass);
+        }
+
+        self
+    }
+
+    pub fn write(&self, prefix: &str) -> std::io::Result<()> {
+        let mut passes = vec![(String::from("emission"), &self.emission)];
+        for lobe in LOBES.iter() {
+            passes.push((format!("{}_direct", lobe_name(*lobe)), &self.direct[*lobe as usize]));
+            passes.push((format!("{}_indirect", lobe_name(*lobe)), &self.indirect[*lobe as usize]));
+        }
+        for (name, film) in self.light_groups.iter() {
+            passes.push((format!("light_{}", name), film));
+        }
+
+        for (name, film) in passes.iter() {
+            let mut f = BufWriter::new(File::create(format!("{}.{}.pfm", prefix, name))?);
+            film.write_pfm(&mut f)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,24 +1,38 @@
 use std::borrow::BorrowMut;
+use std::env;
 use std::io;
 use std::path::Path;
+use std::process;
 use std::rc::Rc;
 
 use crate::aarect::{XyRect, XzRect, YzRect};
+use crate::aov::Aovs;
 use crate::block::Block;
 use crate::bvh_node::{BvhNode};
-use crate::camera::Camera;
-use crate::color::{Color, write_color};
+use crate::camera::{ApertureShape, Focus, Lens, Orthographic, Perspective, Projection};
+use crate::camera_path::{CameraPath, Keyframe, Spline};
+use crate::color::{Color, ColorEncoding, ColorSpace};
 use crate::hittable::{HitRecord, Hittable, RotateY, Translate};
+use crate::heterogeneous_medium::HeterogeneousMedium;
 use crate::hittable_list::HittableList;
-use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::light_path::{LightPasses, PathRadiance};
+use crate::material::{BumpMap, Conductor, Dielectric, DiffuseLight, Lambertian, Lobe, MediumBoundary, Metal, NormalMap, Principled, ScatterRecord, Subsurface};
+use crate::medium::{Medium, MediumStack};
 use crate::moving_sphere::MovingSphere;
+use crate::perlin::Perlin;
 use crate::point3::Point3;
 use crate::random::{random, random_in_range};
 use crate::ray::Ray;
+use crate::spectrum::{Dispersion, SpectralSampler, Wavelengths};
 use crate::sphere::Sphere;
-use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor};
+use crate::stereo::{StereoLayout, StereoRig};
+use crate::texture::{CheckerTexture, GridTexture, ImageTexture, Interpolation, NoiseTexture, RemapTexture, SolidColor, TurbulenceTexture, WrapMode};
 use crate::vec3::Vec3;
 use crate::constant_medium::ConstantMedium;
+use crate::denoise::denoise;
+use crate::film::SplatFilm;
+use crate::options::{Options, USAGE};
+use crate::phase::PhaseFunction;
 
 mod vec3;
 mod color;
@@ -29,6 +43,7 @@ mod sphere;
 mod hittable_list;
 mod random;
 mod camera;
+mod camera_path;
 mod material;
 mod moving_sphere;
 mod aabb;
@@ -38,29 +53,148 @@ mod perlin;
 mod aarect;
 mod block;
 mod constant_medium;
+mod heterogeneous_medium;
+mod onb;
+mod microfacet;
+mod phase;
+mod tonemap;
+mod film;
+mod filter;
+mod options;
+mod aov;
+mod denoise;
+mod light_path;
+mod spectrum;
+mod medium;
+mod stereo;
+
+// With `wavelengths`, the path is traced spectrally: colours are upsampled as they are met, and the
+// result is converted back to RGB. The first surface the camera ray hits is returned alongside, for
+// the AOVs.
+#[allow(clippy::too_many_arguments)]
+fn ray_color(r: &Ray, background: &Color, world: &dyn Hittable, camera_medium: Option<&Rc<Medium>>, wavelengths: Option<&Wavelengths>, working_space: ColorSpace, max_depth: u32) -> (PathRadiance, Option<HitRecord>) {
+    let reflectance = |c: &Color| match wavelengths {
+        Some(wavelengths) => wavelengths.reflectance(c),
+        None => working_space.linear_srgb_to_working(c),
+    };
+    let illuminant = |c: &Color| match wavelengths {
+        Some(wavelengths) => wavelengths.illuminant(c),
+        None => working_space.linear_srgb_to_working(c),
+    };
+    let to_rgb = |c: Color| match wavelengths {
+        Some(wavelengths) => working_space.linear_srgb_to_working(&wavelengths.to_rgb(&c)),
+        None => c,
+    };
+
+    let mut radiance = PathRadiance::new();
+    let mut first_hit = None;
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut first_lobe = None;
+    let mut media = MediumStack::new(camera_medium);
+    let mut dispersed = false;
+    let mut ray = r.clone();
+
+    // Counts scattering events; crossing into or out of a medium doesn't count.
+    let mut bounce = 0;
+    while bounce < max_depth {
+        let mut rec = HitRecord::new();
+        let hit = world.hit(&ray, 0.001, f64::INFINITY, &mut rec);
+
+        // Surfaces that only bound a medium, or that bound one the path is inside something
+        // stronger than, are crossed without scattering.
+        let interior = rec.mat_ptr.as_ref().and_then(|material| material.interior_medium());
+        let crossing = match (hit, interior.as_ref()) {
+            (true, Some(medium)) => rec.mat_ptr.as_ref().unwrap().is_medium_boundary() || media.outranks(medium),
+            _ => false,
+        };
+        if hit {
+            rec.footprint = ray.cone_width_at(rec.t);
+            if bounce == 0 && !crossing && first_hit.is_none() {
+                first_hit = Some(rec.clone());
+            }
+        }
 
-fn ray_color(r: &Ray, background: &Color, world: &dyn Hittable, depth: u32) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+        if let Some(medium) = media.current() {
+            let (t, weight) = medium.sample(&ray, if hit { rec.t } else { f64::INFINITY }, reflectance);
+            throughput = throughput * weight;
 
-    let mut rec = HitRecord::new();
-    if !world.hit(r, 0.001, f64::INFINITY, &mut rec) {
-        return *background;
-    }
+            if let Some(t) = t {
+                if bounce == 0 {
+                    first_lobe = Some(Lobe::Volume);
+                }
+                bounce += 1;
+                let footprint = ray.cone_width_at(t);
+                ray = Ray::new(ray.at(t), medium.scatter_direction(&ray.direction()), ray.time())
+                    .with_cone(footprint, ray.cone_angle)
+                    .with_wavelength(ray.wavelength);
+                continue;
+            }
+        }
 
-    let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0);
-    let mut attenuation = Color::new(0.0, 0.0, 0.0);
-    let emitted = rec.mat_ptr.as_ref().unwrap().emitted(rec.u, rec.v, &rec.p);
+        if !hit {
+            radiance.add(to_rgb(throughput * illuminant(background)), bounce, first_lobe, "background");
+            break;
+        }
+
+        if crossing {
+            media.cross(interior.as_ref().unwrap(), rec.front_face);
+            ray = Ray::new(rec.p, ray.direction(), ray.time())
+                .with_cone(rec.footprint, ray.cone_angle)
+                .with_wavelength(ray.wavelength);
+            continue;
+        }
+
+        let material = rec.mat_ptr.clone().unwrap();
+
+        let emitted = illuminant(&material.emitted(rec.u, rec.v, &rec.p));
+        radiance.add(to_rgb(throughput * emitted), bounce, first_lobe, material.light_group().unwrap_or("default"));
+
+        let mut srec = ScatterRecord::new();
+        if !material.scatter(&ray, &rec, &mut srec) {
+            break;
+        }
 
-    if !rec.mat_ptr.as_ref().unwrap().scatter(r, &rec, &mut attenuation, &mut scattered) {
-        return emitted;
+        if let (Some(medium), Lobe::Transmission) = (interior.as_ref(), srec.lobe) {
+            media.cross(medium, rec.front_face);
+        }
+
+        // Once the path has followed the hero wavelength through a dispersive interface, the other
+        // wavelengths can't share it, so they are dropped and the hero carries the whole estimate.
+        if srec.dispersive && !dispersed {
+            dispersed = true;
+            throughput = Color::new(3.0 * throughput.x(), 0.0, 0.0);
+        }
+
+        if bounce == 0 {
+            first_lobe = Some(srec.lobe);
+        }
+        bounce += 1;
+        throughput = throughput * reflectance(&srec.attenuation);
+        ray = srec.scattered
+            .with_cone(rec.footprint, ray.cone_angle)
+            .with_wavelength(ray.wavelength);
     }
 
-    emitted + attenuation * ray_color(&scattered, background, world, depth - 1)
+    (radiance, first_hit)
 }
 
 fn random_scene() -> HittableList {
+    random_scene_with_glass(|_| Dielectric::new(1.5))
+}
+
+// The same spheres as `random_scene`, with the glass ones frosted and the large one tinted.
+fn frosted_random_scene() -> HittableList {
+    random_scene_with_glass(|radius| {
+        if radius < 1.0 {
+            Dielectric::new_rough(1.5, 0.3)
+        } else {
+            Dielectric::new(1.5).with_tint(Rc::new(SolidColor::new(Color::new(0.4, 0.8, 0.6))), 1.0)
+        }
+    })
+}
+
+// `glass` makes the material for a glass sphere of the given radius.
+fn random_scene_with_glass(glass: impl Fn(f64) -> Dielectric) -> HittableList {
     let mut objects: Vec<Rc<dyn Hittable>> = Vec::new();
 
     let checker = Rc::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
@@ -83,14 +217,14 @@ fn random_scene() -> HittableList {
                     let sphere_material = Rc::new(Metal::new(albedo, fuzz));
                     objects.push(Rc::new(Sphere::new(center, 0.2, sphere_material.clone())));
                 } else {
-                    let sphere_material = Rc::new(Dielectric::new(1.5));
+                    let sphere_material = Rc::new(glass(0.2));
                     objects.push(Rc::new(Sphere::new(center, 0.2, sphere_material.clone())));
                 }
             }
         }
     }
 
-    let material1 = Dielectric::new(1.5);
+    let material1 = glass(1.0);
     objects.push(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Rc::new(material1))));
 
     let material2 = Lambertian::new(Color::new(0.4, 0.2, 0.1));
@@ -128,7 +262,10 @@ fn two_perlin_spheres() -> HittableList {
 }
 
 fn earth() -> HittableList {
-    let earth_texture = Rc::new(ImageTexture::new(Path::new("earthmap.jpg")));
+    let earth_texture = Rc::new(ImageTexture::new(Path::new("earthmap.jpg"))
+        .with_interpolation(Interpolation::Bicubic)
+        .with_wrap(WrapMode::Repeat)
+        .with_mipmaps());
     let earth_surface = Rc::new(Lambertian::new_from_texture(earth_texture));
     let globe = Rc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, earth_surface));
 
@@ -145,7 +282,7 @@ fn simple_light() -> HittableList {
     world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new_from_texture(pertext.clone())))));
     world.add(Rc::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, Rc::new(Lambertian::new_from_texture(pertext.clone())))));
 
-    world.add(Rc::new(XyRect::new(3.0, 5.0, 1.0, 3.0, -2.0, Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(4.0, 4.0, 4.0))))))));
+    world.add(Rc::new(XyRect::new(3.0, 5.0, 1.0, 3.0, -2.0, Rc::new(DiffuseLight::new_in_group(Rc::new(SolidColor::new(Color::new(4.0, 4.0, 4.0))), "key")))));
 
     world
 }
@@ -155,7 +292,7 @@ fn cornell_box() -> HittableList {
     let white = Rc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
     let green = Rc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
 
-    let light = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(15.0, 15.0, 15.0)))));
+    let light = Rc::new(DiffuseLight::new_in_group(Rc::new(SolidColor::new(Color::new(15.0, 15.0, 15.0))), "ceiling"));
 
     let mut world = HittableList::new();
 
@@ -186,7 +323,7 @@ fn cornell_smoke() -> HittableList {
     let white = Rc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
     let green = Rc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
 
-    let light = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0)))));
+    let light = Rc::new(DiffuseLight::new_in_group(Rc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0))), "ceiling"));
 
     let mut world = HittableList::new();
 
@@ -212,7 +349,275 @@ fn cornell_smoke() -> HittableList {
     world
 }
 
+fn metals() -> HittableList {
+    let checker = Rc::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new_from_texture(checker)))));
+
+    world.add(Rc::new(Sphere::new(Point3::new(-3.3, 1.0, 0.0), 1.0, Rc::new(Conductor::gold(0.1)))));
+    world.add(Rc::new(Sphere::new(Point3::new(-1.1, 1.0, 0.0), 1.0, Rc::new(Conductor::silver(0.3)))));
+    world.add(Rc::new(Sphere::new(Point3::new(1.1, 1.0, 0.0), 1.0, Rc::new(Conductor::copper(0.2)))));
+    world.add(Rc::new(Sphere::new(Point3::new(3.3, 1.0, 0.0), 1.0, Rc::new(Conductor::aluminium(0.5)))));
+
+    let brushed = Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), 0.4, 0.8);
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, -3.0), 1.0, Rc::new(brushed))));
+
+    let scratches = Rc::new(RemapTexture::new(Rc::new(NoiseTexture::new(8.0)), 0.05, 0.6));
+    let scratched_copper = Conductor::copper(0.0).with_roughness(scratches.clone());
+    world.add(Rc::new(Sphere::new(Point3::new(-3.0, 1.0, -3.5), 1.0, Rc::new(scratched_copper))));
+
+    let pitted = Metal::new_from_texture(Rc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))), scratches);
+    world.add(Rc::new(Sphere::new(Point3::new(3.0, 1.0, -3.5), 1.0, Rc::new(pitted))));
+
+    world
+}
+
+fn glass() -> HittableList {
+    let checker = Rc::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new_from_texture(checker)))));
+
+    let clear = Dielectric::new(1.5);
+    world.add(Rc::new(Sphere::new(Point3::new(-3.3, 1.0, 0.0), 1.0, Rc::new(clear))));
+
+    let frosted = Dielectric::new_rough(1.5, 0.4);
+    world.add(Rc::new(Sphere::new(Point3::new(-1.1, 1.0, 0.0), 1.0, Rc::new(frosted))));
+
+    let tinted = Dielectric::new(1.5).with_tint(Rc::new(SolidColor::new(Color::new(0.2, 0.6, 0.3))), 1.0);
+    world.add(Rc::new(Sphere::new(Point3::new(1.1, 1.0, 0.0), 1.0, Rc::new(tinted))));
+
+    let ior_map = Rc::new(RemapTexture::new(Rc::new(NoiseTexture::new(2.0)), 1.2, 1.8));
+    let graded = Dielectric::new_from_texture(ior_map).with_roughness(Rc::new(SolidColor::new_scalar(0.05)));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, -3.0), 1.0, Rc::new(graded))));
+
+    let bubble = Dielectric::new(1.33).thin_walled();
+    world.add(Rc::new(Sphere::new(Point3::new(3.3, 1.0, 0.0), 1.0, Rc::new(bubble))));
+
+    world
+}
+
+fn principled() -> HittableList {
+    let checker = Rc::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new_from_texture(checker)))));
+
+    let car_paint = Principled {
+        roughness: Rc::new(SolidColor::new_scalar(0.4)),
+        clearcoat: Rc::new(SolidColor::new_scalar(1.0)),
+        ..Principled::new(Rc::new(SolidColor::new(Color::new(0.6, 0.05, 0.05))))
+    };
+    world.add(Rc::new(Sphere::new(Point3::new(-4.4, 1.0, 0.0), 1.0, Rc::new(car_paint))));
+
+    let velvet = Principled {
+        roughness: Rc::new(SolidColor::new_scalar(1.0)),
+        specular: Rc::new(SolidColor::new_scalar(0.0)),
+        sheen: Rc::new(SolidColor::new_scalar(1.0)),
+        ..Principled::new(Rc::new(SolidColor::new(Color::new(0.2, 0.05, 0.3))))
+    };
+    world.add(Rc::new(Sphere::new(Point3::new(-2.2, 1.0, 0.0), 1.0, Rc::new(velvet))));
+
+    let scratched_gold = Principled {
+        metallic: Rc::new(SolidColor::new_scalar(1.0)),
+        roughness: Rc::new(NoiseTexture::new(4.0)),
+        ..Principled::new(Rc::new(SolidColor::new(Color::new(1.0, 0.78, 0.34))))
+    };
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Rc::new(scratched_gold))));
+
+    let frosted_glass = Principled {
+        roughness: Rc::new(SolidColor::new_scalar(0.2)),
+        transmission: Rc::new(SolidColor::new_scalar(1.0)),
+        ..Principled::new(Rc::new(SolidColor::new(Color::new(0.8, 0.9, 1.0))))
+    };
+    world.add(Rc::new(Sphere::new(Point3::new(2.2, 1.0, 0.0), 1.0, Rc::new(frosted_glass))));
+
+    let lamp = Principled {
+        emission: Rc::new(SolidColor::new(Color::new(2.0, 1.6, 1.0))),
+        ..Principled::new(Rc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))))
+    };
+    world.add(Rc::new(Sphere::new(Point3::new(4.4, 1.0, 0.0), 1.0, Rc::new(lamp))));
+
+    world
+}
+
+fn bump_mapping() -> HittableList {
+    let pertext = Rc::new(NoiseTexture::new(4.0));
+    let bricks = Rc::new(ImageTexture::new_with_encoding(Path::new("bricks_normal.png"), ColorEncoding::Linear)
+        .with_interpolation(Interpolation::Bilinear)
+        .with_wrap(WrapMode::Mirror)
+        .with_mipmaps());
+    let wall_bricks = Rc::new(ImageTexture::new_with_encoding(Path::new("bricks_normal.png"), ColorEncoding::Linear)
+        .with_interpolation(Interpolation::Bilinear)
+        .with_wrap(WrapMode::Repeat)
+        .with_uv_transform((3.0, 1.5), (0.0, 0.0))
+        .with_mipmaps());
+
+    let mut world = HittableList::new();
+
+    let ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(BumpMap::new(ground, pertext.clone(), 0.02)))));
+
+    let marble = Rc::new(Lambertian::new_from_texture(pertext.clone()));
+    world.add(Rc::new(Sphere::new(Point3::new(-2.2, 1.0, 0.0), 1.0, Rc::new(BumpMap::new(marble, pertext, 0.05)))));
+
+    let tiles = Rc::new(Conductor::copper(0.3));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Rc::new(NormalMap::new(tiles, bricks.clone())))));
+
+    let wall = Rc::new(Lambertian::new(Color::new(0.6, 0.3, 0.2)));
+    world.add(Rc::new(XyRect::new(-6.0, 6.0, 0.0, 6.0, -3.0, Rc::new(NormalMap::new(wall, wall_bricks)))));
+
+    world.add(Rc::new(Sphere::new(Point3::new(2.2, 1.0, 0.0), 1.0, Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0)))));
+
+    world
+}
+
+fn cornell_clouds() -> HittableList {
+    let red = Rc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Rc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Rc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    let light = Rc::new(DiffuseLight::new_in_group(Rc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0))), "ceiling"));
+
+    let mut world = HittableList::new();
+
+    world.add(Rc::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
+    world.add(Rc::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
+
+    world.add(Rc::new(XzRect::new(113.0, 443.0, 127.0, 432.0, 554.0, light)));
+
+    world.add(Rc::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())));
+    world.add(Rc::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())));
+    world.add(Rc::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())));
+
+    // A fog bank hugging the floor, thinned out by turbulence.
+    let fog = Rc::new(Block::new(Point3::new(0.0, 0.0, 0.0), Point3::new(555.0, 120.0, 555.0), white.clone()));
+    let fog_density = Rc::new(TurbulenceTexture::new(0.02, 7));
+    world.add(Rc::new(HeterogeneousMedium::new(fog, fog_density, 0.03, Rc::new(SolidColor::new_scalar(0.9))).with_phase(PhaseFunction::HenyeyGreenstein(0.6))));
+
+    // A smoke plume baked into a density grid, widening as it rises.
+    let min = Point3::new(180.0, 0.0, 180.0);
+    let max = Point3::new(380.0, 450.0, 380.0);
+    let n = 48;
+    let noise = Perlin::new();
+    let mut values = Vec::with_capacity(n * n * n);
+    for k in 0..n {
+        for j in 0..n {
+            for i in 0..n {
+                let x = i as f64 / (n - 1) as f64 - 0.5;
+                let y = j as f64 / (n - 1) as f64;
+                let z = k as f64 / (n - 1) as f64 - 0.5;
+
+                let radius = 0.1 + 0.35 * y;
+                let falloff = f64::max(0.0, 1.0 - f64::sqrt(x * x + z * z) / radius) * (1.0 - y);
+                let wisps = noise.turb(&Point3::new(4.0 * x, 3.0 * y, 4.0 * z), Option::None);
+                let d = f64::min(1.0, falloff * 4.0 * wisps);
+                values.push(Color::new(d, d, d));
+            }
+        }
+    }
+    let plume = Rc::new(Block::new(min, max, white.clone()));
+    let plume_density = Rc::new(GridTexture::new(n, n, n, min, max, values));
+    world.add(Rc::new(HeterogeneousMedium::new(plume, plume_density, 0.2, Rc::new(SolidColor::new_scalar(0.8)))
+        .with_phase(PhaseFunction::DoubleHenyeyGreenstein { g1: 0.8, g2: -0.3, weight: 0.7 })));
+
+    // Two separate blobs of tinted haze sharing one boundary, which the medium sees as non-convex.
+    // The haze scatters with the Rayleigh phase function, as much backwards as forwards.
+    let mut blobs = HittableList::new();
+    blobs.add(Rc::new(Sphere::new(Point3::new(110.0, 380.0, 200.0), 70.0, white.clone())));
+    blobs.add(Rc::new(Sphere::new(Point3::new(445.0, 380.0, 200.0), 70.0, white.clone())));
+    world.add(Rc::new(ConstantMedium::new_colored(Rc::new(blobs), Color::new(0.002, 0.01, 0.02), Color::new(0.02, 0.015, 0.01))
+        .with_phase(PhaseFunction::Rayleigh)));
+
+    world
+}
+
+fn nested_media(water: Rc<Medium>) -> HittableList {
+    let mut world = HittableList::new();
+
+    // The camera sits inside a pool, so the surface is seen from below.
+    let pool = Rc::new(Dielectric::new(1.33).with_medium(water));
+    world.add(Rc::new(Block::new(Point3::new(-50.0, -10.0, -50.0), Point3::new(50.0, 0.0, 50.0), pool)));
+    world.add(Rc::new(XzRect::new(-50.0, 50.0, -50.0, 50.0, -3.0, Rc::new(Lambertian::new(Color::new(0.76, 0.7, 0.5))))));
+
+    // A glass ball of wine floating half submerged. The glass outranks the water, so the part of
+    // the pool surface inside the glass is ignored, and the wine outranks the glass.
+    let glass = Rc::new(Medium::new(Color::new(0.02, 0.0, 0.02), Color::new(0.0, 0.0, 0.0)).with_priority(2));
+    let wine = Rc::new(Medium::new(Color::new(0.05, 1.5, 1.2), Color::new(0.0, 0.0, 0.0)).with_priority(3));
+    world.add(Rc::new(Sphere::new(Point3::new(-1.2, 0.0, 0.0), 1.0, Rc::new(Dielectric::new(1.5).with_medium(glass.clone())))));
+    world.add(Rc::new(Sphere::new(Point3::new(-1.2, 0.0, 0.0), 0.85, Rc::new(Dielectric::new(1.33 / 1.5).with_medium(wine)))));
+
+    // A sunken bottle full of smoke.
+    let smoke = Rc::new(Medium::new(Color::new(0.1, 0.1, 0.1), Color::new(3.0, 3.0, 3.0))
+        .with_phase(PhaseFunction::HenyeyGreenstein(0.4))
+        .with_priority(3));
+    world.add(Rc::new(Sphere::new(Point3::new(1.5, -1.8, -0.5), 1.0, Rc::new(Dielectric::new(1.5).with_medium(glass)))));
+    world.add(Rc::new(Sphere::new(Point3::new(1.5, -1.8, -0.5), 0.9, Rc::new(MediumBoundary::new(smoke)))));
+
+    world
+}
+
+fn dispersion() -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))))));
+
+    world.add(Rc::new(Sphere::new(Point3::new(-1.3, 1.0, 0.0), 1.0, Rc::new(Dielectric::new(1.5).with_dispersion(Dispersion::flint())))));
+    world.add(Rc::new(Sphere::new(Point3::new(1.3, 1.0, 0.0), 1.0, Rc::new(Dielectric::new(1.5).with_dispersion(Dispersion::diamond())))));
+
+    // A small, bright light behind the gems throws coloured caustics towards the camera.
+    let light = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(30.0, 30.0, 30.0)))));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 5.0, -6.0), 1.0, light)));
+
+    world
+}
+
+fn subsurface() -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new(Color::new(0.3, 0.3, 0.3))))));
+
+    let skin = Subsurface::new(Color::new(0.85, 0.6, 0.5), Color::new(0.8, 0.4, 0.25), 1.4);
+    let wax = Subsurface::new(Color::new(0.95, 0.8, 0.5), Color::new(0.6, 0.6, 0.4), 1.45);
+    let marble = Subsurface::new(Color::new(0.9, 0.9, 0.88), Color::new(0.3, 0.3, 0.3), 1.5);
+    world.add(Rc::new(Sphere::new(Point3::new(-2.2, 1.0, 0.0), 1.0, Rc::new(skin))));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Rc::new(wax))));
+    world.add(Rc::new(Sphere::new(Point3::new(2.2, 1.0, 0.0), 1.0, Rc::new(marble))));
+
+    // Backlighting shows light bleeding through the thin edges.
+    let light = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(8.0, 8.0, 8.0)))));
+    world.add(Rc::new(XyRect::new(-4.0, 4.0, 3.5, 6.0, -4.0, light)));
+
+    world
+}
+
+fn bokeh() -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Rc::new(Lambertian::new(Color::new(0.2, 0.2, 0.2))))));
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Rc::new(Metal::new(Color::new(0.9, 0.75, 0.4), 0.05)))));
+
+    // Small, bright lights well behind the subject, which blur into the shape of the aperture.
+    for _ in 0..60 {
+        let center = Point3::new(random_in_range(-12.0, 12.0), random_in_range(0.5, 8.0), random_in_range(-30.0, -20.0));
+        let emit = 50.0 * Color::random_in_range(0.3, 1.0);
+        world.add(Rc::new(Sphere::new(center, 0.08, Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(emit)))))));
+    }
+
+    let key = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(4.0, 4.0, 4.0)))));
+    world.add(Rc::new(XzRect::new(-2.0, 2.0, -1.0, 3.0, 6.0, key)));
+
+    world
+}
+
 fn final_scene() -> HittableList {
+    final_scene_with_glass(Dielectric::new(1.5))
+}
+
+// The same scene as `final_scene`, with the glass sphere frosted.
+fn frosted_final_scene() -> HittableList {
+    final_scene_with_glass(Dielectric::new_rough(1.5, 0.2))
+}
+
+fn final_scene_with_glass(glass: Dielectric) -> HittableList {
     let mut boxes1: Vec<Rc<dyn Hittable>> = Vec::new();
     let ground = Rc::new(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
 
@@ -234,7 +639,7 @@ fn final_scene() -> HittableList {
     let mut objects = HittableList::new();
     objects.add(Rc::new(BvhNode::new_from_list(&mut boxes1)));
 
-    let light = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0)))));
+    let light = Rc::new(DiffuseLight::new_in_group(Rc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0))), "ceiling"));
     objects.add(Rc::new(XzRect::new(123.0, 423.0, 147.0, 412.0, 554.0, light)));
 
     let center1 = Point3::new(400.0, 400.0, 200.0);
@@ -242,17 +647,20 @@ fn final_scene() -> HittableList {
     let moving_sphere_material = Rc::new(Lambertian::new(Color::new(0.7, 0.3, 0.1)));
     objects.add(Rc::new(MovingSphere::new(center1, center2, 0.0, 1.0, 50.0, moving_sphere_material)));
 
-    objects.add(Rc::new(Sphere::new(Point3::new(260.0, 150.0, 45.0), 50.0, Rc::new(Dielectric::new(1.5)))));
+    objects.add(Rc::new(Sphere::new(Point3::new(260.0, 150.0, 45.0), 50.0, Rc::new(glass))));
     objects.add(Rc::new(Sphere::new(Point3::new(0.0, 150.0, 145.0), 50.0, Rc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0)))));
 
-    let boundary = Rc::new(Sphere::new(Point3::new(360.0, 150.0, 145.0), 70.0, Rc::new(Dielectric::new(1.5))));
-    objects.add(boundary.clone());
-    objects.add(Rc::new(ConstantMedium::new(boundary.clone(), 0.2, Rc::new(SolidColor::new(Color::new(0.2, 0.4, 0.9))))));
+    let smoke = Rc::new(Medium::new(0.2 * Color::new(0.8, 0.6, 0.1), 0.2 * Color::new(0.2, 0.4, 0.9)));
+    objects.add(Rc::new(Sphere::new(Point3::new(360.0, 150.0, 145.0), 70.0, Rc::new(Dielectric::new(1.5).with_medium(smoke)))));
 
     let boundary = Rc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 5000.0, Rc::new(Dielectric::new(1.5))));
     objects.add(Rc::new(ConstantMedium::new(boundary.clone(), 0.0001, Rc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))))));
 
-    let emat = Rc::new(Lambertian::new_from_texture(Rc::new(ImageTexture::new(Path::new("earthmap.jpg")))));
+    let earth_texture = ImageTexture::new(Path::new("earthmap.jpg"))
+        .with_interpolation(Interpolation::Bilinear)
+        .with_wrap(WrapMode::Repeat)
+        .with_mipmaps();
+    let emat = Rc::new(Lambertian::new_from_texture(Rc::new(earth_texture)));
     objects.add(Rc::new(Sphere::new(Point3::new(400.0, 200.0, 400.0), 100.0, emat)));
 
     let pertext = Rc::new(NoiseTexture::new(0.1));
@@ -271,6 +679,14 @@ fn final_scene() -> HittableList {
 }
 
 fn main() {
+    let options = match Options::from_args(env::args()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
     let aspect_ratio;
 
     let image_width;
@@ -283,6 +699,16 @@ fn main() {
     let vfov;
     let aperture;
     let background;
+    let working_space = options.working_space.unwrap_or(ColorSpace::LinearSrgb);
+    let mut camera_medium = None;
+    let mut projection: Option<Rc<dyn Projection>> = None;
+    let mut focus = Focus::LookAt;
+    let mut lens = None;
+    let mut camera_path = None;
+    // Scene units per metre, which sets the physical camera's depth of field. The Cornell box scenes
+    // are 555 units across, where the real box is about 0.55 m, and the final scene is built to the
+    // same scale.
+    let mut scene_scale = 1.0;
 
     match 0 {
         1 => {
@@ -295,6 +721,7 @@ fn main() {
             lookat = Point3::new(0.0, 0.0, 0.0);
             vfov = 20.0;
             aperture = 0.1;
+            focus = Focus::Distance(10.0);
         }
         2 => {
             world = two_spheres();
@@ -348,6 +775,7 @@ fn main() {
             background = Color::new(0.0, 0.0, 0.0);
             lookfrom = Point3::new(278.0, 278.0, -800.0);
             lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
             vfov = 40.0;
             aperture = 0.0;
         }
@@ -359,10 +787,154 @@ fn main() {
             background = Color::new(0.0, 0.0, 0.0);
             lookfrom = Point3::new(278.0, 278.0, -800.0);
             lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
+            vfov = 40.0;
+            aperture = 0.0;
+        }
+        9 => {
+            world = metals();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 100;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(0.0, 3.0, 12.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 30.0;
+            aperture = 0.0;
+        }
+        10 => {
+            world = glass();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 100;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(0.0, 3.0, 12.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 30.0;
+            aperture = 0.0;
+        }
+        11 => {
+            world = principled();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 100;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(0.0, 3.0, 14.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 30.0;
+            aperture = 0.0;
+        }
+        12 => {
+            world = bump_mapping();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 100;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(0.0, 3.0, 12.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 30.0;
+            aperture = 0.0;
+        }
+        13 => {
+            world = cornell_clouds();
+            aspect_ratio = 1.0;
+            image_width = 600;
+            samples_per_pixel = 200;
+            background = Color::new(0.0, 0.0, 0.0);
+            lookfrom = Point3::new(278.0, 278.0, -800.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
             vfov = 40.0;
             aperture = 0.0;
         }
-        8 | _ => {
+        14 => {
+            let water = Rc::new(Medium::new(Color::new(0.12, 0.04, 0.02), Color::new(0.02, 0.02, 0.02)).with_priority(1));
+            world = nested_media(water.clone());
+            camera_medium = Some(water);
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 500;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(0.0, -1.0, 7.0);
+            lookat = Point3::new(0.0, -0.6, 0.0);
+            vfov = 40.0;
+            aperture = 0.0;
+        }
+        15 => {
+            world = dispersion();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 1000;
+            background = Color::new(0.01, 0.01, 0.01);
+            lookfrom = Point3::new(0.0, 4.0, 9.0);
+            lookat = Point3::new(0.0, 0.5, 0.0);
+            vfov = 35.0;
+            aperture = 0.0;
+        }
+        16 => {
+            world = subsurface();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 500;
+            background = Color::new(0.05, 0.05, 0.08);
+            lookfrom = Point3::new(0.0, 2.5, 10.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 30.0;
+            aperture = 0.0;
+        }
+        17 => {
+            // A front elevation of the Cornell box, without perspective.
+            world = cornell_box();
+            aspect_ratio = 1.0;
+            image_width = 600;
+            samples_per_pixel = 200;
+            background = Color::new(0.0, 0.0, 0.0);
+            lookfrom = Point3::new(278.0, 278.0, -800.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
+            vfov = 40.0;
+            aperture = 0.0;
+            projection = Some(Rc::new(Orthographic::new(555.0, aspect_ratio)));
+        }
+        18 => {
+            world = bokeh();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 500;
+            background = Color::new(0.0, 0.0, 0.0);
+            lookfrom = Point3::new(0.0, 1.5, 7.0);
+            lookat = Point3::new(0.0, 1.0, 0.0);
+            vfov = 25.0;
+            aperture = 0.8;
+            lens = Some(Lens::new()
+                .with_aperture_shape(ApertureShape::Polygon { blades: 6, rotation: 90.0 })
+                .with_cats_eye(0.5));
+        }
+        19 => {
+            world = frosted_random_scene();
+            aspect_ratio = 16.0 / 9.0;
+            image_width = 400;
+            samples_per_pixel = 100;
+            background = Color::new(0.70, 0.80, 1.00);
+            lookfrom = Point3::new(13.0, 2.0, 3.0);
+            lookat = Point3::new(0.0, 0.0, 0.0);
+            vfov = 20.0;
+            aperture = 0.1;
+            focus = Focus::Distance(10.0);
+        }
+        20 => {
+            world = frosted_final_scene();
+            aspect_ratio = 1.0;
+            image_width = 800;
+            samples_per_pixel = 10000;
+            background = Color::new(0.0, 0.0, 0.0);
+            lookfrom = Point3::new(478.0, 278.0, -600.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
+            vfov = 40.0;
+            aperture = 0.0;
+        }
+        _ => {
             world = final_scene();
             aspect_ratio = 1.0;
             image_width = 800;
@@ -370,32 +942,148 @@ fn main() {
             background = Color::new(0.0, 0.0, 0.0);
             lookfrom = Point3::new(478.0, 278.0, -600.0);
             lookat = Point3::new(278.0, 278.0, 0.0);
+            scene_scale = 1000.0;
             vfov = 40.0;
             aperture = 0.0;
+
+            // A fly-through that starts from the still, swings round the back of the scene and
+            // comes out on the far side.
+            camera_path = Some(CameraPath::new(vec![
+                Keyframe::new(0.0, lookfrom, lookat, vfov),
+                Keyframe::new(24.0, Point3::new(200.0, 320.0, -500.0), Point3::new(278.0, 278.0, 200.0), 40.0),
+                Keyframe::new(48.0, Point3::new(-100.0, 350.0, -200.0), Point3::new(278.0, 278.0, 200.0), 40.0)
+                    .with_vup(Vec3::new(0.1, 1.0, 0.0)),
+                Keyframe::new(60.0, Point3::new(-200.0, 400.0, 278.0), Point3::new(278.0, 278.0, 278.0), 45.0),
+                Keyframe::new(72.0, Point3::new(-100.0, 450.0, 700.0), Point3::new(278.0, 278.0, 278.0), 45.0),
+                Keyframe::new(84.0, Point3::new(300.0, 500.0, 900.0), Point3::new(278.0, 250.0, 278.0), 40.0)
+                    .with_focus_dist(600.0),
+                Keyframe::new(96.0, Point3::new(700.0, 400.0, 700.0), Point3::new(278.0, 278.0, 278.0), 35.0),
+            ], Spline::CatmullRom));
         }
     }
 
-    let image_height = (image_width as f64 / aspect_ratio) as u32;
+    // With a frame to render, a scene's camera path sets the pose for that frame.
+    let pose = match (camera_path, options.frame) {
+        (Some(camera_path), Some(frame)) => {
+            let camera_path = match options.spline {
+                Some(spline) => camera_path.with_spline(spline),
+                None => camera_path,
+            };
+            camera_path.with_easing(options.easing).evaluate(frame)
+        }
+        _ => Keyframe::new(0.0, lookfrom, lookat, vfov).with_focus_dist(focus.distance(lookfrom, lookat)),
+    };
+    let (lookfrom, lookat) = (pose.lookfrom, pose.lookat);
+    let pose = match options.focus {
+        Some(focus) => pose.with_focus_dist(focus.distance(lookfrom, lookat)),
+        None => pose,
+    };
+
+    if let Some(panorama) = options.panorama {
+        projection = Some(panorama.projection());
+    }
+    let aspect_ratio = options.panorama.map_or(aspect_ratio, |panorama| panorama.aspect_ratio());
+
+    // A physical camera replaces the scene's field of view and aperture, and sets the exposure and
+    // how long the shutter is open.
+    let mut tone_mapping = options.tone_mapping;
+    let (pose, aperture, shutter_interval) = match options.physical_camera {
+        Some(physical_camera) => {
+            let physical_camera = physical_camera.with_scene_scale(options.scene_scale.unwrap_or(scene_scale));
+            tone_mapping.exposure += physical_camera.exposure();
+            (pose.with_vfov(physical_camera.vfov(aspect_ratio)), physical_camera.aperture(), physical_camera.shutter_interval())
+        }
+        None => (pose, aperture, 1.0),
+    };
 
-    let dist_to_focus = 10.0;
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let cam = Camera::new(lookfrom, lookat, vup, vfov, aspect_ratio, aperture, dist_to_focus, 0.0, 1.0);
+    if let (None, Some(lens)) = (projection.as_ref(), options.lens.as_ref().or(lens.as_ref())) {
+        projection = Some(Rc::new(Perspective::new(pose.vfov, aspect_ratio, aperture, pose.focus_dist).with_lens(lens.clone())));
+    }
 
-    print!("P3\n{} {}\n255\n", image_width, image_height);
+    // A stereo rig renders both eyes side by side or one above the other, each at the scene's size.
+    // By default the eyes converge on `lookat`, and are set apart by a thirtieth of that distance.
+    let (image_width, image_aspect_ratio) = match options.stereo {
+        Some(layout) => StereoRig::image_size(layout, image_width, aspect_ratio),
+        None => (image_width, aspect_ratio),
+    };
+    let image_height = (image_width as f64 / image_aspect_ratio) as u32;
+    if let Some(layout) = options.stereo {
+        let eye = projection.take()
+            .unwrap_or_else(|| Rc::new(Perspective::new(pose.vfov, aspect_ratio, aperture, pose.focus_dist)));
+        let convergence = options.convergence.unwrap_or_else(|| (lookat - lookfrom).length());
+        let ipd = options.ipd.unwrap_or(convergence / 30.0);
+        projection = Some(Rc::new(StereoRig::new(eye, layout, (image_width, image_height), ipd, convergence, options.panorama.is_some())));
+    }
+    let cam = pose.camera(projection, aspect_ratio, aperture, 0.0, shutter_interval);
+    let cam = cam.with_shutter_curve(options.shutter_curve).with_rolling_shutter(options.rolling_shutter);
+
+    let (cone_width, cone_angle) = cam.pixel_footprint(image_height);
+
+    // Samples of one eye of a stereo pair don't spill into the other.
+    let mut film = match options.stereo {
+        Some(StereoLayout::SideBySide) => SplatFilm::new(image_width, image_height, options.filter).with_tiles(2, 1),
+        Some(StereoLayout::TopBottom) => SplatFilm::new(image_width, image_height, options.filter).with_tiles(1, 2),
+        None => SplatFilm::new(image_width, image_height, options.filter),
+    };
+    let spectral_sampler = if options.spectral { Some(SpectralSampler::new()) } else { None };
+    let mut light_passes = options.light_pass_prefix.as_ref().map(|_| LightPasses::new(image_width, image_height));
+    let mut aovs = if options.aov_prefix.is_some() || options.denoise {
+        Some(Aovs::new(image_width, image_height))
+    } else {
+        None
+    };
+    let world = if aovs.is_some() { world.with_object_ids() } else { world };
 
     for j in (0..image_height).rev() {
         eprint!("\rScanlines remaining: {} ", j);
         for i in 0..image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..samples_per_pixel {
-                let u = (i as f64 + random::random()) / (image_width - 1) as f64;
-                let v = (j as f64 + random::random()) as f64 / (image_height - 1) as f64;
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &background, &world, max_depth);
+            for s in 0..samples_per_pixel {
+                let (dx, dy) = (random::random(), random::random());
+                let u = (i as f64 + dx) / (image_width - 1) as f64;
+                let v = (j as f64 + dy) / (image_height - 1) as f64;
+                // Where the sample lies on the film, counting rows from the top.
+                let (x, y) = (i as f64 + dx, (image_height - j) as f64 - dy);
+
+                let wavelengths = spectral_sampler.as_ref().map(|sampler| sampler.sample());
+                let r = match cam.get_ray(u, v) {
+                    Some(r) => r,
+                    None => {
+                        // Samples the lens blocks still count, as black.
+                        film.add_sample(x, y, &Color::new(0.0, 0.0, 0.0));
+                        continue;
+                    }
+                };
+                let r = r
+                    .with_cone(cone_width, cone_angle)
+                    .with_wavelength(wavelengths.as_ref().map(|w| w.hero()));
+                let (radiance, rec) = ray_color(&r, &background, &world, camera_medium.as_ref(), wavelengths.as_ref(), working_space, max_depth);
+                film.add_sample(x, y, &radiance.total);
+
+                if let Some(light_passes) = light_passes.as_mut() {
+                    light_passes.add_sample(&film, x, y, &radiance);
+                }
+
+                if let Some(aovs) = aovs.as_mut() {
+                    aovs.add_sample(i, image_height - 1 - j, rec.as_ref(), 1.0 / samples_per_pixel as f64, s == 0, working_space);
+                }
             }
-            write_color(io::stdout().borrow_mut(), pixel_color, samples_per_pixel).unwrap();
         }
     }
+    let light_passes = light_passes.map(|light_passes| light_passes.resolve(&film));
+    let mut film = film.resolve();
+
+    if let (true, Some(aovs)) = (options.denoise, aovs.as_ref()) {
+        eprint!("\nDenoising.");
+        film = denoise(&film, &aovs.albedo, &aovs.normal);
+    }
+
+    film.write_ppm(io::stdout().borrow_mut(), &tone_mapping, working_space).unwrap();
+    if let (Some(aovs), Some(prefix)) = (aovs, options.aov_prefix) {
+        aovs.write(&prefix).unwrap();
+    }
+    if let (Some(light_passes), Some(prefix)) = (light_passes, options.light_pass_prefix) {
+        light_passes.write(&prefix).unwrap();
+    }
 
     eprint!("\nDone.\n");
 }
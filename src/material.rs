@@ -1,19 +1,75 @@
 use std::rc::Rc;
 
-use crate::color::Color;
+use crate::color::{clamp, Color};
 use crate::hittable::HitRecord;
+use crate::medium::Medium;
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::phase::PhaseFunction;
 use crate::point3::Point3;
 use crate::random::random;
+use crate::spectrum::Dispersion;
 use crate::ray::Ray;
 use crate::texture::{SolidColor, Texture};
 use crate::vec3::Vec3;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+    Transmission,
+    Volume,
+}
+
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub lobe: Lobe,
+    // Set when the scattering depended on the ray's wavelength.
+    pub dispersive: bool,
+}
+
+impl ScatterRecord {
+    pub fn new() -> ScatterRecord {
+        ScatterRecord {
+            attenuation: Color::new(0.0, 0.0, 0.0),
+            scattered: Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0),
+            lobe: Lobe::Diffuse,
+            dispersive: false,
+        }
+    }
+}
+
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool;
 
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+
+    // Surface colour as seen by the albedo pass, independent of lighting.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+
+    // Name of the light group that emission from this material is written to.
+    fn light_group(&self) -> Option<&str> {
+        None
+    }
+
+    // Medium that fills the inside of objects made from this material.
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        None
+    }
+
+    // Whether the surface only marks where its interior medium begins, with nothing to scatter off.
+    fn is_medium_boundary(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
@@ -35,121 +91,682 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let scatter_direction = rec.normal + Vec3::random_unit_vector();
-        *scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.scattered = Ray::new(rec.p, scatter_direction, r_in.time());
+        srec.attenuation = self.albedo.sample(rec);
+        srec.lobe = Lobe::Diffuse;
 
         true
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.sample(rec)
+    }
 }
 
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    albedo: Rc<dyn Texture>,
+    fuzz: Rc<dyn Texture>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, f: f64) -> Self {
+        Metal {
+            albedo: Rc::new(SolidColor::new(albedo)),
+            fuzz: Rc::new(SolidColor::new_scalar(f)),
+        }
+    }
+
+    pub fn new_from_texture(albedo: Rc<dyn Texture>, fuzz: Rc<dyn Texture>) -> Self {
         Metal {
             albedo,
-            fuzz: f.min(1.0),
+            fuzz,
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let fuzz = self.fuzz.scalar_value(rec.u, rec.v, &rec.p).min(1.0);
         let reflected = Vec3::reflect(&Vec3::unit_vector(r_in.direction()), &rec.normal);
-        *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere(), r_in.time());
-        *attenuation = self.albedo;
+        srec.scattered = Ray::new(rec.p, reflected + fuzz * Vec3::random_in_unit_sphere(), r_in.time());
+        srec.attenuation = self.albedo.sample(rec);
+        srec.lobe = Lobe::Specular;
+
+        Vec3::dot(&srec.scattered.direction(), &rec.geometric_normal) > 0.0
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.sample(rec)
+    }
+}
+
+pub struct Conductor {
+    eta: Rc<dyn Texture>,
+    k: Rc<dyn Texture>,
+    roughness: Rc<dyn Texture>,
+    anisotropy: Rc<dyn Texture>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        Conductor::new_from_texture(
+            Rc::new(SolidColor::new(eta)),
+            Rc::new(SolidColor::new(k)),
+            Rc::new(SolidColor::new_scalar(roughness)),
+            Rc::new(SolidColor::new_scalar(anisotropy)),
+        )
+    }
+
+    pub fn new_from_texture(eta: Rc<dyn Texture>, k: Rc<dyn Texture>, roughness: Rc<dyn Texture>, anisotropy: Rc<dyn Texture>) -> Self {
+        Conductor {
+            eta,
+            k,
+            roughness,
+            anisotropy,
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Conductor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), roughness, 0.0)
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Conductor::new(Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147), roughness, 0.0)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Conductor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), roughness, 0.0)
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), roughness, 0.0)
+    }
+
+    pub fn with_roughness(self, roughness: Rc<dyn Texture>) -> Self {
+        Conductor {
+            roughness,
+            ..self
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let uvw = Onb::new_from_tangents(&rec.normal, &rec.tangent, &rec.bitangent);
+        let wo = uvw.to_local(&-Vec3::unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let distribution = TrowbridgeReitz::new(
+            self.roughness.scalar_value(rec.u, rec.v, &rec.p),
+            self.anisotropy.scalar_value(rec.u, rec.v, &rec.p),
+        );
+        let wm = distribution.sample_wm(&wo);
+        let wi = Vec3::reflect(&-wo, &wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        let eta = self.eta.value(rec.u, rec.v, &rec.p);
+        let k = self.k.value(rec.u, rec.v, &rec.p);
+
+        srec.scattered = Ray::new(rec.p, uvw.local(&wi), r_in.time());
+        srec.attenuation = fresnel_conductor(Vec3::dot(&wo, &wm), &eta, &k) * (distribution.g(&wo, &wi) / distribution.g1(&wo));
+        srec.lobe = Lobe::Specular;
+
+        true
+    }
 
-        Vec3::dot(&scattered.direction(), &rec.normal) > 0.0
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let eta = self.eta.value(rec.u, rec.v, &rec.p);
+        let k = self.k.value(rec.u, rec.v, &rec.p);
+        fresnel_conductor(1.0, &eta, &k)
     }
 }
 
 pub struct Dielectric {
-    ir: f64,
+    ir: Rc<dyn Texture>,
+    roughness: Option<Rc<dyn Texture>>,
+    tint: Option<(Rc<dyn Texture>, f64)>,
+    thin_walled: bool,
+    medium: Option<Rc<Medium>>,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
+        Dielectric::new_from_texture(Rc::new(SolidColor::new_scalar(ir)))
+    }
+
+    pub fn new_from_texture(ir: Rc<dyn Texture>) -> Self {
         Dielectric {
             ir,
+            roughness: None,
+            tint: None,
+            thin_walled: false,
+            medium: None,
+            dispersion: None,
         }
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-        let r0 = r0 * r0;
-        r0 + (1.0 - r0) * f64::powi(1.0 - cosine, 5)
+    pub fn new_rough(ir: f64, roughness: f64) -> Self {
+        Dielectric::new(ir).with_roughness(Rc::new(SolidColor::new_scalar(roughness)))
+    }
+
+    pub fn with_roughness(self, roughness: Rc<dyn Texture>) -> Self {
+        Dielectric {
+            roughness: Some(roughness),
+            ..self
+        }
+    }
+
+    // Light travelling `distance` through the interior is filtered down to `color`.
+    // The colour is looked up where the light leaves the interior.
+    pub fn with_tint(self, color: Rc<dyn Texture>, distance: f64) -> Self {
+        assert!(distance > 0.0, "Tint distance must be positive");
+        Dielectric {
+            tint: Some((color, distance)),
+            ..self
+        }
+    }
+
+    pub fn thin_walled(self) -> Self {
+        Dielectric {
+            thin_walled: true,
+            ..self
+        }
+    }
+
+    // Replaces the index of refraction with one that varies with wavelength. Without a spectral
+    // wavelength on the ray, the index at the sodium d-line is used.
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Self {
+        Dielectric {
+            dispersion: Some(dispersion),
+            ..self
+        }
+    }
+
+    pub fn with_medium(self, medium: Rc<Medium>) -> Self {
+        Dielectric {
+            medium: Some(medium),
+            ..self
+        }
+    }
+
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        match &self.tint {
+            None => Color::new(1.0, 1.0, 1.0),
+            Some((color, tint_distance)) => {
+                let color = color.value(rec.u, rec.v, &rec.p);
+                let channel = |c: f64| f64::powf(clamp(c, 1e-6, 1.0), distance / tint_distance);
+                Color::new(channel(color.x()), channel(color.y()), channel(color.z()))
+            }
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.attenuation = if rec.front_face || self.thin_walled {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance(rec, rec.t * r_in.direction().length())
+        };
+
+        let ir = match (&self.dispersion, r_in.wavelength) {
+            (Some(dispersion), Some(wavelength)) => {
+                srec.dispersive = true;
+                dispersion.ior(wavelength)
+            }
+            (Some(dispersion), None) => dispersion.ior(589.3),
+            (None, _) => self.ir.scalar_value(rec.u, rec.v, &rec.p),
+        };
+        let eta = if rec.front_face || self.thin_walled {
+            ir
         } else {
-            self.ir
+            1.0 / ir
+        };
+
+        let distribution = self.roughness.as_ref()
+            .map(|roughness| TrowbridgeReitz::new(roughness.scalar_value(rec.u, rec.v, &rec.p), 0.0));
+
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-Vec3::unit_vector(r_in.direction()));
+        let wm = match &distribution {
+            Some(distribution) => distribution.sample_wm(&wo),
+            None => Vec3::new(0.0, 0.0, 1.0),
         };
 
-        let unit_direction = Vec3::unit_vector(r_in.direction());
-        let cos_theta = f64::min(Vec3::dot(&-unit_direction, &rec.normal), 1.0);
-        let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
+        let cos_theta = Vec3::dot(&wo, &wm);
+        let r = fresnel_dielectric(cos_theta, eta);
+        let reflectance = if self.thin_walled && r < 1.0 {
+            r + (1.0 - r) * (1.0 - r) * r / (1.0 - r * r)
+        } else {
+            r
+        };
 
-        let direction = if refraction_ratio * sin_theta > 1.0 || Dielectric::reflectance(cos_theta, refraction_ratio) > random() {
-            Vec3::reflect(&unit_direction, &rec.normal)
+        let reflect = reflectance > random();
+        let reflected = Vec3::reflect(&-wo, &wm);
+        let wi = if reflect {
+            reflected
+        } else if self.thin_walled {
+            Vec3::new(reflected.x(), reflected.y(), -reflected.z())
         } else {
-            Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
+            Vec3::refract(&-wo, &wm, 1.0 / eta)
         };
 
-        *scattered = Ray::new(rec.p, direction, r_in.time());
+        if let Some(distribution) = &distribution {
+            if (wi.z() > 0.0) != reflect {
+                return false;
+            }
+            srec.attenuation *= distribution.g(&wo, &wi) / distribution.g1(&wo);
+        }
+
+        srec.scattered = Ray::new(rec.p, uvw.local(&wi), r_in.time());
+        srec.lobe = if reflect {
+            Lobe::Specular
+        } else {
+            Lobe::Transmission
+        };
 
         true
     }
+
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        self.medium.clone()
+    }
+}
+
+// A translucent material such as skin, wax or marble: a dielectric boundary over a scattering
+// interior that light random walks through. `albedo` is the colour the object should appear and
+// `mean_free_path` how far light travels between scattering events, both per channel.
+pub struct Subsurface {
+    albedo: Color,
+    boundary: Dielectric,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color, ir: f64) -> Self {
+        let sigma_t = Color::new(1.0 / mean_free_path.x(), 1.0 / mean_free_path.y(), 1.0 / mean_free_path.z());
+        let single_scattering = Color::new(
+            single_scattering_albedo(albedo.x()),
+            single_scattering_albedo(albedo.y()),
+            single_scattering_albedo(albedo.z()),
+        );
+        let sigma_s = single_scattering * sigma_t;
+        let medium = Medium::new(sigma_t - sigma_s, sigma_s);
+
+        Subsurface {
+            albedo,
+            boundary: Dielectric::new(ir).with_medium(Rc::new(medium)),
+        }
+    }
+}
+
+// Inverts the multiple scattering albedo of a random walk into the albedo of a single scattering
+// event (Chiang et al. 2016).
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let t = 4.09712 + 4.20863 * albedo - f64::sqrt(9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo);
+    1.0 - t * t
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.boundary.scatter(r_in, rec, srec)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        self.boundary.interior_medium()
+    }
+}
+
+pub struct Principled {
+    pub base_color: Rc<dyn Texture>,
+    pub metallic: Rc<dyn Texture>,
+    pub roughness: Rc<dyn Texture>,
+    pub specular: Rc<dyn Texture>,
+    pub sheen: Rc<dyn Texture>,
+    pub clearcoat: Rc<dyn Texture>,
+    pub transmission: Rc<dyn Texture>,
+    pub emission: Rc<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new(base_color: Rc<dyn Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic: Rc::new(SolidColor::new_scalar(0.0)),
+            roughness: Rc::new(SolidColor::new_scalar(0.5)),
+            specular: Rc::new(SolidColor::new_scalar(0.5)),
+            sheen: Rc::new(SolidColor::new_scalar(0.0)),
+            clearcoat: Rc::new(SolidColor::new_scalar(0.0)),
+            transmission: Rc::new(SolidColor::new_scalar(0.0)),
+            emission: Rc::new(SolidColor::new_scalar(0.0)),
+        }
+    }
+
+    fn scalar(texture: &Rc<dyn Texture>, rec: &HitRecord) -> f64 {
+        clamp(texture.scalar_value(rec.u, rec.v, &rec.p), 0.0, 1.0)
+    }
+
+    fn schlick_weight(cosine: f64) -> f64 {
+        f64::powi(1.0 - clamp(cosine, 0.0, 1.0), 5)
+    }
+}
+
+// Lobes are picked stochastically, top to bottom: clearcoat, metal, dielectric specular, then
+// transmission or diffuse with sheen. Each layer only receives the energy the layer above did not
+// reflect, so the combined albedo never exceeds one.
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let base_color = self.base_color.sample(rec);
+        let metallic = Principled::scalar(&self.metallic, rec);
+        let roughness = Principled::scalar(&self.roughness, rec);
+        let specular = Principled::scalar(&self.specular, rec);
+        let sheen = Principled::scalar(&self.sheen, rec);
+        let clearcoat = Principled::scalar(&self.clearcoat, rec);
+        let transmission = if rec.front_face {
+            Principled::scalar(&self.transmission, rec)
+        } else {
+            1.0
+        };
+
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-Vec3::unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let distribution = TrowbridgeReitz::new(roughness, 0.0);
+
+        let (wi, weight, lobe) = if rec.front_face && random() < clearcoat * fresnel_dielectric(wo.z(), 1.5) {
+            let coat = TrowbridgeReitz::new(0.1, 0.0);
+            let wm = coat.sample_wm(&wo);
+            let wi = Vec3::reflect(&-wo, &wm);
+            (wi, Color::new(1.0, 1.0, 1.0) * (coat.g(&wo, &wi) / coat.g1(&wo)), Lobe::Specular)
+        } else if rec.front_face && random() < metallic {
+            let wm = distribution.sample_wm(&wo);
+            let wi = Vec3::reflect(&-wo, &wm);
+            let fresnel = base_color + (Color::new(1.0, 1.0, 1.0) - base_color) * Principled::schlick_weight(Vec3::dot(&wo, &wm));
+            (wi, fresnel * (distribution.g(&wo, &wi) / distribution.g1(&wo)), Lobe::Specular)
+        } else {
+            let f0 = f64::sqrt(0.08 * specular);
+            let ior = (1.0 + f0) / (1.0 - f0);
+            let eta = if rec.front_face {
+                ior
+            } else {
+                1.0 / ior
+            };
+
+            let wm = distribution.sample_wm(&wo);
+            if random() < fresnel_dielectric(Vec3::dot(&wo, &wm), eta) {
+                let wi = Vec3::reflect(&-wo, &wm);
+                (wi, Color::new(1.0, 1.0, 1.0) * (distribution.g(&wo, &wi) / distribution.g1(&wo)), Lobe::Specular)
+            } else if random() < transmission {
+                let wi = Vec3::refract(&-wo, &wm, 1.0 / eta);
+                let tint = if rec.front_face {
+                    base_color
+                } else {
+                    Color::new(1.0, 1.0, 1.0)
+                };
+                (wi, tint * (distribution.g(&wo, &wi) / distribution.g1(&wo)), Lobe::Transmission)
+            } else {
+                let wi = Vec3::unit_vector(Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector());
+                let wh = Vec3::unit_vector(wi + wo);
+                let sheen_weight = sheen * Principled::schlick_weight(Vec3::dot(&wi, &wh));
+                (wi, base_color * (1.0 - sheen_weight) + Color::new(1.0, 1.0, 1.0) * sheen_weight, Lobe::Diffuse)
+            }
+        };
+
+        if (wi.z() > 0.0) != (lobe != Lobe::Transmission) {
+            return false;
+        }
+
+        srec.scattered = Ray::new(rec.p, uvw.local(&wi), r_in.time());
+        srec.attenuation = weight;
+        srec.lobe = lobe;
+
+        true
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emission.value(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.sample(rec)
+    }
 }
 
 pub struct DiffuseLight {
     emit: Rc<dyn Texture>,
+    group: Option<String>,
 }
 
 impl DiffuseLight {
     pub fn new(emit: Rc<dyn Texture>) -> Self {
         DiffuseLight {
-            emit
+            emit,
+            group: None,
+        }
+    }
+
+    pub fn new_in_group(emit: Rc<dyn Texture>, group: &str) -> Self {
+        DiffuseLight {
+            emit,
+            group: Some(group.to_string()),
         }
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _attenuation: &mut Color, _scattered: &mut Ray) -> bool {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
         false
     }
 
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
         self.emit.value(u, v, p)
     }
+
+    fn light_group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
 }
 
-pub struct Isotropic {
+// The scattering inside a medium, with directions drawn from a phase function.
+pub struct Volume {
     albedo: Rc<dyn Texture>,
+    phase: PhaseFunction,
 }
 
-impl Isotropic {
-    pub fn new(a: Rc<dyn Texture>) -> Self {
-        Isotropic {
+impl Volume {
+    pub fn new(a: Rc<dyn Texture>, phase: PhaseFunction) -> Self {
+        Volume {
             albedo: a,
+            phase,
         }
     }
 }
 
-impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+impl Material for Volume {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.scattered = Ray::new(rec.p, self.phase.sample(&r_in.direction()), r_in.time());
+        srec.attenuation = self.albedo.sample(rec);
+        srec.lobe = Lobe::Volume;
 
         true
     }
-}
\ No newline at end of file
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.sample(rec)
+    }
+}
+
+// An invisible surface that only marks where its interior medium begins, such as smoke filling a
+// bottle.
+pub struct MediumBoundary {
+    medium: Rc<Medium>,
+}
+
+impl MediumBoundary {
+    pub fn new(medium: Rc<Medium>) -> Self {
+        MediumBoundary {
+            medium,
+        }
+    }
+}
+
+impl Material for MediumBoundary {
+    // Paths cross the boundary without scattering off it.
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+        false
+    }
+
+    fn is_medium_boundary(&self) -> bool {
+        true
+    }
+
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        Some(self.medium.clone())
+    }
+}
+
+// Whether a direction scattered about a perturbed shading normal leaves the real surface the way its
+// lobe meant to, rather than reflecting into the object or transmitting back out of it.
+fn leaves_on_its_side(rec: &HitRecord, srec: &ScatterRecord) -> bool {
+    let above = Vec3::dot(&srec.scattered.direction(), &rec.geometric_normal) > 0.0;
+    above != (srec.lobe == Lobe::Transmission)
+}
+
+pub struct BumpMap {
+    material: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(material: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> Self {
+        BumpMap {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn perturbed_normal(&self, rec: &HitRecord) -> Vec3 {
+        let delta = 0.0005;
+        let displace = |u: f64, v: f64, p: &Point3| self.scale * self.height.scalar_value(u, v, p);
+
+        let d = displace(rec.u, rec.v, &rec.p);
+        let du = (displace(rec.u + delta, rec.v, &(rec.p + delta * rec.tangent)) - d) / delta;
+        let dv = (displace(rec.u, rec.v + delta, &(rec.p + delta * rec.bitangent)) - d) / delta;
+
+        let dpdu = rec.tangent + du * rec.normal;
+        let dpdv = rec.bitangent + dv * rec.normal;
+        let n = Vec3::cross(&dpdu, &dpdv);
+        if n.length_squared() == 0.0 {
+            return rec.normal;
+        }
+
+        let n = Vec3::unit_vector(n);
+        if Vec3::dot(&n, &rec.normal) < 0.0 {
+            -n
+        } else {
+            n
+        }
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut shading = rec.clone();
+        shading.normal = self.perturbed_normal(rec);
+        self.material.scatter(r_in, &shading, srec) && leaves_on_its_side(rec, srec)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.material.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.perturbed_normal(rec)
+    }
+
+    fn light_group(&self) -> Option<&str> {
+        self.material.light_group()
+    }
+
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        self.material.interior_medium()
+    }
+}
+
+pub struct NormalMap {
+    material: Rc<dyn Material>,
+    normals: Rc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(material: Rc<dyn Material>, normals: Rc<dyn Texture>) -> Self {
+        NormalMap {
+            material,
+            normals,
+        }
+    }
+
+    fn perturbed_normal(&self, rec: &HitRecord) -> Vec3 {
+        let outward = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let tbn = Onb::new_from_tangents(&outward, &rec.tangent, &rec.bitangent);
+
+        let c = self.normals.value(rec.u, rec.v, &rec.p);
+        let n = Vec3::unit_vector(tbn.local(&(2.0 * c - Vec3::new(1.0, 1.0, 1.0))));
+        if rec.front_face {
+            n
+        } else {
+            -n
+        }
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut shading = rec.clone();
+        shading.normal = self.perturbed_normal(rec);
+        self.material.scatter(r_in, &shading, srec) && leaves_on_its_side(rec, srec)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.material.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.perturbed_normal(rec)
+    }
+
+    fn light_group(&self) -> Option<&str> {
+        self.material.light_group()
+    }
+
+    fn interior_medium(&self) -> Option<Rc<Medium>> {
+        self.material.interior_medium()
+    }
+}
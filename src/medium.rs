@@ -0,0 +1,178 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::constant_medium::sample_free_flight;
+use crate::phase::PhaseFunction;
+use crate::random::{random, random_usize_in_range};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// A homogeneous medium filling the inside of an object, tracked per path rather than bounded by
+// its own geometry. Where the insides of several objects overlap, the medium with the highest
+// priority wins and the surfaces of the others are ignored.
+pub struct Medium {
+    sigma_a: Color,
+    sigma_s: Color,
+    phase: PhaseFunction,
+    pub priority: i32,
+}
+
+impl Medium {
+    // `sigma_a` and `sigma_s` are the absorption and scattering coefficients per colour channel.
+    pub fn new(sigma_a: Color, sigma_s: Color) -> Self {
+        Medium {
+            sigma_a,
+            sigma_s,
+            phase: PhaseFunction::Isotropic,
+            priority: 0,
+        }
+    }
+
+    pub fn with_phase(self, phase: PhaseFunction) -> Self {
+        Medium {
+            phase,
+            ..self
+        }
+    }
+
+    pub fn with_priority(self, priority: i32) -> Self {
+        Medium {
+            priority,
+            ..self
+        }
+    }
+
+    // Samples a scattering event along `r` before `t_max`. Returns where it happened, or None if the
+    // ray reached `t_max`, along with the path weight. The distance is sampled from a randomly chosen
+    // channel and weighted by the average over all three. `to_path` converts the coefficients into
+    // the colour representation the path is traced in.
+    pub fn sample(&self, r: &Ray, t_max: f64, to_path: impl Fn(&Color) -> Color) -> (Option<f64>, Color) {
+        let sigma_s = to_path(&self.sigma_s);
+        let sigma_t = to_path(&self.sigma_a) + sigma_s;
+        let ray_length = r.direction().length();
+
+        let channel = random_usize_in_range(0, 3);
+        let t = if sigma_t[channel] > 0.0 {
+            sample_free_flight(sigma_t[channel] * ray_length, random())
+        } else {
+            f64::INFINITY
+        };
+
+        let distance = f64::min(t, t_max) * ray_length;
+        let transmittance = Color::new(
+            transmittance(sigma_t.x(), distance),
+            transmittance(sigma_t.y(), distance),
+            transmittance(sigma_t.z(), distance),
+        );
+
+        if t < t_max {
+            let density = sigma_t * transmittance;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            (Some(t), sigma_s * transmittance / pdf)
+        } else {
+            let pdf = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.0;
+            (None, transmittance / pdf)
+        }
+    }
+
+    pub fn scatter_direction(&self, dir: &Vec3) -> Vec3 {
+        self.phase.sample(dir)
+    }
+}
+
+// Clear channels transmit everything, even over the infinite distance of a ray that escapes.
+fn transmittance(sigma_t: f64, distance: f64) -> f64 {
+    if sigma_t > 0.0 {
+        f64::exp(-sigma_t * distance)
+    } else {
+        1.0
+    }
+}
+
+// The media a path is currently inside, in the order it entered them.
+pub struct MediumStack {
+    media: Vec<Rc<Medium>>,
+}
+
+impl MediumStack {
+    pub fn new(camera_medium: Option<&Rc<Medium>>) -> Self {
+        MediumStack {
+            media: camera_medium.into_iter().cloned().collect(),
+        }
+    }
+
+    // The medium with the highest priority, or the most recently entered of those tied for it.
+    pub fn current(&self) -> Option<&Rc<Medium>> {
+        self.strongest_except(None)
+    }
+
+    // Whether the path is inside a medium with higher priority than `medium`, making the boundary of
+    // `medium` a false intersection.
+    pub fn outranks(&self, medium: &Rc<Medium>) -> bool {
+        match self.strongest_except(Some(medium)) {
+            Some(strongest) => strongest.priority > medium.priority,
+            None => false,
+        }
+    }
+
+    pub fn cross(&mut self, medium: &Rc<Medium>, entering: bool) {
+        if entering {
+            self.media.push(medium.clone());
+        } else if let Some(i) = self.media.iter().rposition(|m| Rc::ptr_eq(m, medium)) {
+            self.media.remove(i);
+        }
+    }
+
+    fn strongest_except(&self, except: Option<&Rc<Medium>>) -> Option<&Rc<Medium>> {
+        self.media.iter()
+            .filter(|m| !matches!(except, Some(e) if Rc::ptr_eq(m, e)))
+            .max_by_key(|m| m.priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(priority: i32) -> Rc<Medium> {
+        Rc::new(Medium::new(Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0)).with_priority(priority))
+    }
+
+    #[test]
+    fn higher_priorities_hide_the_boundaries_inside_them() {
+        let water = clear(1);
+        let glass = clear(2);
+        let wine = clear(3);
+        let mut media = MediumStack::new(Some(&water));
+
+        // Each boundary, whether the path enters it, and whether the path crosses it without
+        // scattering, for a path from the water into the glass and the wine that fills it, out through
+        // the water's surface and then the wine's, which overlaps the glass.
+        let path = [
+            (&glass, true, false),
+            (&wine, true, false),
+            (&water, false, true),
+            (&glass, false, true),
+            (&wine, false, false),
+        ];
+        for (medium, entering, crossed) in path.iter() {
+            assert_eq!(media.outranks(medium), *crossed);
+            media.cross(medium, *entering);
+        }
+
+        assert!(media.current().is_none());
+    }
+
+    #[test]
+    fn ties_go_to_the_most_recently_entered_medium() {
+        let outer = clear(1);
+        let inner = clear(1);
+        let mut media = MediumStack::new(Some(&outer));
+        media.cross(&inner, true);
+        assert!(Rc::ptr_eq(media.current().unwrap(), &inner));
+        assert!(!media.outranks(&inner));
+
+        media.cross(&inner, false);
+        assert!(Rc::ptr_eq(media.current().unwrap(), &outer));
+    }
+}
@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+use crate::color::{clamp, Color};
+use crate::random::random;
+use crate::vec3::Vec3;
+
+// Directions are in the local shading frame, with the normal along +z.
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = f64::max(roughness * roughness, 1e-4);
+        let aspect = f64::sqrt(1.0 - 0.9 * anisotropy);
+
+        TrowbridgeReitz {
+            alpha_x: f64::max(alpha / aspect, 1e-4),
+            alpha_y: f64::max(alpha * aspect, 1e-4),
+        }
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let ax = self.alpha_x * w.x();
+        let ay = self.alpha_y * w.y();
+        0.5 * (f64::sqrt(1.0 + (ax * ax + ay * ay) / (w.z() * w.z())) - 1.0)
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible from `wo` (Heitz 2018).
+    pub fn sample_wm(&self, wo: &Vec3) -> Vec3 {
+        let vh = Vec3::unit_vector(Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()));
+
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / f64::sqrt(lensq)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&vh, &t1);
+
+        let r = f64::sqrt(random());
+        let phi = 2.0 * PI * random();
+        let p1 = r * f64::cos(phi);
+        let p2 = r * f64::sin(phi);
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * f64::sqrt(1.0 - p1 * p1) + s * p2;
+
+        let nh = p1 * t1 + p2 * t2 + f64::sqrt(f64::max(0.0, 1.0 - p1 * p1 - p2 * p2)) * vh;
+
+        Vec3::unit_vector(Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), f64::max(1e-6, nh.z())))
+    }
+}
+
+// `eta` is the ratio of the refractive index on the transmitted side to the incident side.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = clamp(cos_theta_i, 0.0, 1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = f64::sqrt(1.0 - sin2_theta_t);
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+fn fresnel_conductor_channel(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = f64::sqrt(t0 * t0 + 4.0 * eta2 * k2);
+    let t1 = a2_plus_b2 + cos2;
+    let a = f64::sqrt(f64::max(0.0, 0.5 * (a2_plus_b2 + t0)));
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos_theta_i = clamp(cos_theta_i, 0.0, 1.0);
+    Color::new(
+        fresnel_conductor_channel(cos_theta_i, eta.x(), k.x()),
+        fresnel_conductor_channel(cos_theta_i, eta.y(), k.y()),
+        fresnel_conductor_channel(cos_theta_i, eta.z(), k.z()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(distribution: &TrowbridgeReitz, wm: &Vec3) -> f64 {
+        let (ax, ay) = (distribution.alpha_x, distribution.alpha_y);
+        let e = (wm.x() / ax) * (wm.x() / ax) + (wm.y() / ay) * (wm.y() / ay) + wm.z() * wm.z();
+        1.0 / (PI * ax * ay * e * e)
+    }
+
+    #[test]
+    fn vndf_weight_matches_the_integrated_brdf() {
+        let distribution = TrowbridgeReitz::new(0.5, 0.3);
+        let theta_o = f64::to_radians(60.0);
+        let wo = Vec3::new(f64::sin(theta_o), 0.0, f64::cos(theta_o));
+
+        // The reflectance of a white conductor, integrated over a grid of incoming directions.
+        let (nt, np) = (800, 1600);
+        let (dt, dp) = (0.5 * PI / nt as f64, 2.0 * PI / np as f64);
+        let mut albedo = 0.0;
+        for i in 0..nt {
+            let theta = (i as f64 + 0.5) * dt;
+            for j in 0..np {
+                let phi = (j as f64 + 0.5) * dp;
+                let wi = Vec3::new(f64::sin(theta) * f64::cos(phi), f64::sin(theta) * f64::sin(phi), f64::cos(theta));
+                let wm = Vec3::unit_vector(wi + wo);
+                albedo += d(&distribution, &wm) * distribution.g(&wo, &wi) / (4.0 * wo.z()) * f64::sin(theta) * dt * dp;
+            }
+        }
+
+        // The same, estimated with the weight used for visible normal samples.
+        let n = 200000;
+        let mut estimate = 0.0;
+        for _ in 0..n {
+            let wm = distribution.sample_wm(&wo);
+            let wi = Vec3::reflect(&-wo, &wm);
+            if wi.z() > 0.0 {
+                estimate += distribution.g(&wo, &wi) / distribution.g1(&wo);
+            }
+        }
+        estimate /= n as f64;
+
+        assert!(f64::abs(estimate - albedo) < 0.01, "{} vs {}", estimate, albedo);
+    }
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence() {
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        let f = fresnel_conductor(1.0, &Color::new(eta, eta, eta), &Color::new(k, k, k));
+        assert!(f64::abs(f.x() - expected) < 1e-9);
+    }
+
+    #[test]
+    fn conductor_fresnel_without_absorption_is_dielectric_fresnel() {
+        for &cos_theta in [1.0, 0.8, 0.5, 0.2, 0.05].iter() {
+            let f = fresnel_conductor(cos_theta, &Color::new(1.5, 1.5, 1.5), &Color::new(0.0, 0.0, 0.0));
+            assert!(f64::abs(f.x() - fresnel_dielectric(cos_theta, 1.5)) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn conductor_fresnel_is_total_at_grazing_angles() {
+        let f = fresnel_conductor(0.0, &Color::new(0.2, 0.9, 1.1), &Color::new(3.9, 2.5, 2.1));
+        for c in f.e.iter() {
+            assert!(f64::abs(c - 1.0) < 1e-9);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::point3::Point3;
 use crate::ray::Ray;
+use crate::sphere::{get_sphere_tangents, get_sphere_uv};
 use crate::vec3::Vec3;
 
 pub struct MovingSphere {
@@ -50,6 +51,8 @@ impl Hittable for MovingSphere {
                 rec.p = r.at(rec.t);
                 let outward_normal = (rec.p - self.center(r.time())) / self.radius;
                 rec.set_face_normal(r, &outward_normal);
+                get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+                get_sphere_tangents(&outward_normal, self.radius, &mut rec.tangent, &mut rec.bitangent);
                 rec.mat_ptr = Some(self.mat_ptr.clone());
                 return true;
             }
@@ -60,12 +63,14 @@ impl Hittable for MovingSphere {
                 rec.p = r.at(rec.t);
                 let outward_normal = (rec.p - self.center(r.time())) / self.radius;
                 rec.set_face_normal(r, &outward_normal);
+                get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+                get_sphere_tangents(&outward_normal, self.radius, &mut rec.tangent, &mut rec.bitangent);
                 rec.mat_ptr = Some(self.mat_ptr.clone());
                 return true;
             }
         }
 
-        return false;
+        false
     }
 
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
@@ -0,0 +1,62 @@
+use crate::vec3::Vec3;
+
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new_from_w(n: &Vec3) -> Self {
+        let w = Vec3::unit_vector(*n);
+        let a = if f64::abs(w.x()) > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::unit_vector(Vec3::cross(&w, &a));
+        let u = Vec3::cross(&w, &v);
+
+        Onb {
+            axis: [u, v, w],
+        }
+    }
+
+    // `dpdu` and `dpdv` are the surface derivatives: u follows `dpdu`, and v is on the same side
+    // as `dpdv`, so the frame is left-handed where the surface parameterisation is.
+    pub fn new_from_tangents(n: &Vec3, dpdu: &Vec3, dpdv: &Vec3) -> Self {
+        let w = Vec3::unit_vector(*n);
+        let v = Vec3::cross(&w, dpdu);
+        if v.length_squared() < 1e-12 {
+            return Onb::new_from_w(n);
+        }
+
+        let v = Vec3::unit_vector(v);
+        let u = Vec3::cross(&v, &w);
+        let v = if Vec3::dot(&v, dpdv) < 0.0 { -v } else { v };
+
+        Onb {
+            axis: [u, v, w],
+        }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.axis[0] + a.y() * self.axis[1] + a.z() * self.axis[2]
+    }
+
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(Vec3::dot(a, &self.axis[0]), Vec3::dot(a, &self.axis[1]), Vec3::dot(a, &self.axis[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitangent_follows_dpdv() {
+        // An XzRect: u runs along x and v along z, which is left-handed about +y.
+        let uvw = Onb::new_from_tangents(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(2.0, 0.0, 0.0), &Vec3::new(0.0, 0.0, 3.0));
+        assert!((uvw.local(&Vec3::new(1.0, 0.0, 0.0)) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((uvw.local(&Vec3::new(0.0, 1.0, 0.0)) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((uvw.local(&Vec3::new(0.0, 0.0, 1.0)) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    }
+}
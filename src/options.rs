@@ -0,0 +1,302 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::camera::{ApertureShape, Focus, Lens, Panorama, PhysicalCamera, ShutterCurve};
+use crate::camera_path::{Easing, Spline};
+use crate::color::{ColorEncoding, ColorSpace};
+use crate::filter::PixelFilter;
+use crate::point3::Point3;
+use crate::stereo::StereoLayout;
+use crate::texture::ImageTexture;
+use crate::tonemap::{ToneMapOperator, ToneMapping};
+
+pub const USAGE: &str = "\
+Usage: ray-tracing-in-one-weekend-with-rust [options] > image.ppm
+
+Output:
+  --tonemap clamp|reinhard|reinhard-extended|aces|agx
+  --white <luminance>           --exposure <stops>
+  --working-space linear-srgb|acescg
+  --aovs <prefix>               --denoise
+  --light-passes <prefix>       --spectral
+  --filter box|gaussian|mitchell|blackman-harris|lanczos
+  --filter-radius <pixels>
+
+Projection:
+  --panorama equirectangular|fisheye|fisheye-equisolid|cubemap
+  --fisheye-fov <degrees>
+  --stereo side-by-side|top-bottom
+  --ipd <distance>              --convergence <distance>
+
+Physical camera:
+  --focal-length <mm>           --f-stop <number>
+  --sensor <width>x<height>     --shutter <seconds, e.g. 1/60>
+  --iso <speed>                 --fps <frames per second>
+  --scene-scale <units per metre>
+
+Lens:
+  --focus-distance <distance>   --focus-point <x,y,z>
+  --blades <count>              --blade-rotation <degrees>
+  --aperture-image <file>       --cats-eye <amount>
+  --shift <x,y>                 --tilt <degrees>
+
+Motion:
+  --shutter-curve box|triangle|trapezoid
+  --shutter-ramp <fraction>     --rolling-shutter <readout time>
+  --frame <time>                --spline catmull-rom|bezier
+  --easing linear|ease-in|ease-out|ease-in-out
+";
+
+pub struct Options {
+    pub tone_mapping: ToneMapping,
+    pub working_space: Option<ColorSpace>,
+    pub aov_prefix: Option<String>,
+    pub denoise: bool,
+    pub light_pass_prefix: Option<String>,
+    pub spectral: bool,
+    pub panorama: Option<Panorama>,
+    pub stereo: Option<StereoLayout>,
+    pub ipd: Option<f64>,
+    pub convergence: Option<f64>,
+    pub physical_camera: Option<PhysicalCamera>,
+    pub scene_scale: Option<f64>,
+    pub focus: Option<Focus>,
+    pub lens: Option<Lens>,
+    pub shutter_curve: ShutterCurve,
+    pub rolling_shutter: f64,
+    pub frame: Option<f64>,
+    pub spline: Option<Spline>,
+    pub easing: Easing,
+    pub filter: PixelFilter,
+}
+
+impl Options {
+    pub fn from_args(args: impl Iterator<Item=String>) -> Result<Self, String> {
+        let mut operator = String::from("clamp");
+        let mut white = 4.0;
+        let mut exposure = 0.0;
+        let mut working_space = None;
+        let mut aov_prefix = None;
+        let mut denoise = false;
+        let mut light_pass_prefix = None;
+        let mut spectral = false;
+        let mut panorama = None;
+        let mut fisheye_fov = 180.0;
+        let mut stereo = None;
+        let mut ipd = None;
+        let mut convergence = None;
+        let mut focal_length = None;
+        let mut f_number = None;
+        let mut sensor = None;
+        let mut shutter = None;
+        let mut iso = None;
+        let mut frame_rate = None;
+        let mut scene_scale = None;
+        let mut focus = None;
+        let mut blades = None;
+        let mut blade_rotation = 0.0;
+        let mut aperture_image = None;
+        let mut cats_eye = None;
+        let mut shift = None;
+        let mut tilt = None;
+        let mut shutter_curve = String::from("box");
+        let mut shutter_ramp = 0.25;
+        let mut rolling_shutter = 0.0;
+        let mut frame = None;
+        let mut spline = None;
+        let mut easing = String::from("linear");
+        let mut filter = String::from("box");
+        let mut filter_radius = None;
+
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--tonemap" => operator = value()?,
+                "--white" => white = parse(&value()?)?,
+                "--exposure" => exposure = parse(&value()?)?,
+                "--working-space" => working_space = Some(value()?),
+                "--aovs" => aov_prefix = Some(value()?),
+                "--denoise" => denoise = true,
+                "--light-passes" => light_pass_prefix = Some(value()?),
+                "--spectral" => spectral = true,
+                "--panorama" => panorama = Some(value()?),
+                "--fisheye-fov" => fisheye_fov = parse(&value()?)?,
+                "--stereo" => stereo = Some(value()?),
+                "--ipd" => ipd = Some(parse(&value()?)?),
+                "--convergence" => convergence = Some(parse(&value()?)?),
+                "--focal-length" => focal_length = Some(parse(&value()?)?),
+                "--f-stop" => f_number = Some(parse(&value()?)?),
+                "--sensor" => sensor = Some(parse_list(&value()?, 'x', 2)?),
+                "--shutter" => shutter = Some(parse_fraction(&value()?)?),
+                "--iso" => iso = Some(parse(&value()?)?),
+                "--fps" => frame_rate = Some(parse(&value()?)?),
+                "--scene-scale" => scene_scale = Some(parse(&value()?)?),
+                "--focus-distance" => focus = Some(Focus::Distance(parse(&value()?)?)),
+                "--focus-point" => {
+                    let p = parse_list(&value()?, ',', 3)?;
+                    focus = Some(Focus::Point(Point3::new(p[0], p[1], p[2])));
+                }
+                "--blades" => blades = Some(parse(&value()?)?),
+                "--blade-rotation" => blade_rotation = parse(&value()?)?,
+                "--aperture-image" => aperture_image = Some(value()?),
+                "--cats-eye" => cats_eye = Some(parse(&value()?)?),
+                "--shift" => {
+                    let s = parse_list(&value()?, ',', 2)?;
+                    shift = Some((s[0], s[1]));
+                }
+                "--tilt" => tilt = Some(parse(&value()?)?),
+                "--shutter-curve" => shutter_curve = value()?,
+                "--shutter-ramp" => shutter_ramp = parse(&value()?)?,
+                "--rolling-shutter" => rolling_shutter = parse(&value()?)?,
+                "--frame" => frame = Some(parse(&value()?)?),
+                "--spline" => spline = Some(value()?),
+                "--easing" => easing = value()?,
+                "--filter" => filter = value()?,
+                "--filter-radius" => filter_radius = Some(parse(&value()?)?),
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+
+        let operator = ToneMapOperator::from_name(&operator, white)
+            .ok_or_else(|| format!("Unknown tone map operator: {}", operator))?;
+        let working_space = working_space.map(|name| {
+            ColorSpace::from_name(&name).ok_or_else(|| format!("Unknown working space: {}", name))
+        }).transpose()?;
+        let panorama = panorama.map(|name| {
+            Panorama::from_name(&name, fisheye_fov).ok_or_else(|| format!("Unknown panorama: {}", name))
+        }).transpose()?;
+        let shutter_curve = ShutterCurve::from_name(&shutter_curve, shutter_ramp)
+            .ok_or_else(|| format!("Unknown shutter curve: {}", shutter_curve))?;
+        let spline = spline.map(|name| {
+            Spline::from_name(&name).ok_or_else(|| format!("Unknown spline: {}", name))
+        }).transpose()?;
+        let easing = Easing::from_name(&easing).ok_or_else(|| format!("Unknown easing: {}", easing))?;
+        let filter = PixelFilter::from_name(&filter, filter_radius)
+            .ok_or_else(|| format!("Unknown filter: {}", filter))?;
+        // Narrower filters miss parts of each pixel, and pixels that no sample reaches come out black.
+        if filter.radius < 0.5 {
+            return Err(format!("The filter radius must be at least half a pixel: {}", filter.radius));
+        }
+        let stereo = stereo.map(|name| {
+            StereoLayout::from_name(&name).ok_or_else(|| format!("Unknown stereo layout: {}", name))
+        }).transpose()?;
+
+        // These all feed divisions and logarithms in the exposure and field of view.
+        let positive = [
+            ("--focal-length", focal_length),
+            ("--f-stop", f_number),
+            ("--shutter", shutter),
+            ("--iso", iso),
+            ("--fps", frame_rate),
+            ("--scene-scale", scene_scale),
+        ];
+        let sensor_sides = sensor.iter().flatten().map(|&side| ("--sensor", Some(side)));
+        for (name, value) in positive.iter().cloned().chain(sensor_sides) {
+            if let Some(value) = value {
+                if value <= 0.0 || !value.is_finite() {
+                    return Err(format!("{} must be positive and finite: {}", name, value));
+                }
+            }
+        }
+
+        // Any of the lens or exposure settings switches to a physical camera, with a 50mm f/8 lens
+        // unless they are given. The scene scale only applies to a physical camera.
+        let physical_camera = if focal_length.is_some() || f_number.is_some() || sensor.is_some()
+            || shutter.is_some() || iso.is_some() || frame_rate.is_some() {
+            let mut camera = PhysicalCamera::new(focal_length.unwrap_or(50.0), f_number.unwrap_or(8.0));
+            if let Some(sensor) = sensor {
+                camera = camera.with_sensor(sensor[0], sensor[1]);
+            }
+            if let Some(shutter) = shutter {
+                camera = camera.with_shutter(shutter);
+            }
+            if let Some(iso) = iso {
+                camera = camera.with_iso(iso);
+            }
+            if let Some(frame_rate) = frame_rate {
+                camera = camera.with_frame_rate(frame_rate);
+            }
+            Some(camera)
+        } else {
+            None
+        };
+
+        let lens = if blades.is_some() || aperture_image.is_some() || cats_eye.is_some() || shift.is_some() || tilt.is_some() {
+            let mut lens = Lens::new();
+            if blades.is_some() && aperture_image.is_some() {
+                return Err(String::from("--blades and --aperture-image both set the aperture shape"));
+            }
+            if let Some(blades) = blades {
+                if blades < 3 {
+                    return Err(format!("An aperture needs at least 3 blades: {}", blades));
+                }
+                lens = lens.with_aperture_shape(ApertureShape::Polygon { blades, rotation: blade_rotation });
+            }
+            if let Some(filename) = aperture_image {
+                let texture = ImageTexture::open(Path::new(&filename), ColorEncoding::Srgb)?;
+                lens = lens.with_aperture_shape(ApertureShape::from_texture(&texture, 64)?);
+            }
+            if let Some(cats_eye) = cats_eye {
+                lens = lens.with_cats_eye(cats_eye);
+            }
+            if let Some(shift) = shift {
+                lens = lens.with_shift(shift);
+            }
+            if let Some(tilt) = tilt {
+                // At a right angle the plane of focus would pass through the lens.
+                if f64::abs(tilt) >= 90.0 {
+                    return Err(format!("The tilt must be less than 90 degrees either way: {}", tilt));
+                }
+                lens = lens.with_tilt(tilt);
+            }
+            Some(lens)
+        } else {
+            None
+        };
+
+        Ok(Options {
+            tone_mapping: ToneMapping::new(operator, exposure),
+            working_space,
+            aov_prefix,
+            denoise,
+            light_pass_prefix,
+            spectral,
+            panorama,
+            stereo,
+            ipd,
+            convergence,
+            physical_camera,
+            scene_scale,
+            focus,
+            lens,
+            shutter_curve,
+            rolling_shutter,
+            frame,
+            spline,
+            easing,
+            filter,
+        })
+    }
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, String> {
+    s.trim().parse().map_err(|_| format!("Invalid value: {}", s))
+}
+
+// Parses exactly `len` numbers separated by `separator`.
+fn parse_list(s: &str, separator: char, len: usize) -> Result<Vec<f64>, String> {
+    let list = s.split(separator).map(parse).collect::<Result<Vec<f64>, String>>()?;
+    if list.len() != len {
+        return Err(format!("Expected {} values separated by '{}': {}", len, separator, s));
+    }
+    Ok(list)
+}
+
+// Accepts shutter speeds written either as "1/60" or as "0.0167".
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.split_once('/') {
+        Some((numerator, denominator)) => Ok(parse::<f64>(numerator)? / parse::<f64>(denominator)?),
+        None => parse(s),
+    }
+}
@@ -15,8 +15,8 @@ pub struct Perlin {
 impl Perlin {
     pub fn new() -> Self {
         let mut ranvec: [Vec3; 256] = [Vec3::new(0.0, 0.0, 0.0); 256];
-        for i in 0..ranvec.len() {
-            ranvec[i] = Vec3::unit_vector(Vec3::random_in_range(-1.0, 1.0));
+        for v in ranvec.iter_mut() {
+            *v = Vec3::unit_vector(Vec3::random_in_range(-1.0, 1.0));
         }
 
         Perlin {
@@ -28,7 +28,7 @@ impl Perlin {
         }
     }
 
-    pub fn noise(self: &Self, p: &Point3) -> f64 {
+    pub fn noise(&self, p: &Point3) -> f64 {
         let u = p.x() - f64::floor(p.x());
         let v = p.y() - f64::floor(p.y());
         let w = p.z() - f64::floor(p.z());
@@ -55,7 +55,7 @@ impl Perlin {
         perlin_interp(c, u, v, w)
     }
 
-    pub fn turb(self: &Self, p: &Point3, depth: Option<i32>) -> f64 {
+    pub fn turb(&self, p: &Point3, depth: Option<i32>) -> f64 {
         let depth = depth.unwrap_or(7);
 
         let mut accum = 0.0;
@@ -78,15 +78,14 @@ fn perlin_interp(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
     let ww = w * w * (3.0 - 2.0 * w);
 
     let mut accum = 0.0;
-    for i in 0..2 {
-        for j in 0..2 {
-            for k in 0..2 {
+    for (i, ci) in c.iter().enumerate() {
+        for (j, cij) in ci.iter().enumerate() {
+            for (k, cijk) in cij.iter().enumerate() {
                 let weight_v = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
-                accum += 1.0
-                    * (i as f64 * uu + (1.0 - i as f64) * (1.0 - uu))
+                accum += (i as f64 * uu + (1.0 - i as f64) * (1.0 - uu))
                     * (j as f64 * vv + (1.0 - j as f64) * (1.0 - vv))
                     * (k as f64 * ww + (1.0 - k as f64) * (1.0 - ww))
-                    * Vec3::dot(&c[i][j][k], &weight_v);
+                    * Vec3::dot(cijk, &weight_v);
             }
         }
     }
@@ -95,8 +94,8 @@ fn perlin_interp(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
 
 fn perlin_generate_perm() -> [i32; 256] {
     let mut p: [i32; 256] = [0; 256];
-    for i in 0..p.len() {
-        p[i] = i as i32;
+    for (i, v) in p.iter_mut().enumerate() {
+        *v = i as i32;
     }
 
     permute(&mut p, POINT_COUNT);
@@ -107,8 +106,6 @@ fn perlin_generate_perm() -> [i32; 256] {
 fn permute(p: &mut [i32; POINT_COUNT], n: usize) {
     for i in (1..n - 1).rev() {
         let target = random_usize_in_range(0, i);
-        let tmp = p[i];
-        p[i] = p[target];
-        p[target] = tmp;
+        p.swap(i, target);
     }
 }
\ No newline at end of file
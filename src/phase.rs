@@ -0,0 +1,54 @@
+use std::f64::consts::PI;
+
+use crate::onb::Onb;
+use crate::random::random;
+use crate::vec3::Vec3;
+
+// Angular distribution of light scattered inside a medium. The asymmetry parameter g runs from -1
+// (back scattering) through 0 (isotropic) to 1 (forward scattering).
+#[derive(Clone, Copy)]
+pub enum PhaseFunction {
+    Isotropic,
+    HenyeyGreenstein(f64),
+    DoubleHenyeyGreenstein { g1: f64, g2: f64, weight: f64 },
+    Rayleigh,
+}
+
+impl PhaseFunction {
+    // Samples a scattered direction for light travelling along `dir`. The phase functions are
+    // sampled exactly, so no weight is returned.
+    pub fn sample(&self, dir: &Vec3) -> Vec3 {
+        let cos_theta = match *self {
+            PhaseFunction::Isotropic => 1.0 - 2.0 * random(),
+            PhaseFunction::HenyeyGreenstein(g) => sample_henyey_greenstein(g),
+            PhaseFunction::DoubleHenyeyGreenstein { g1, g2, weight } => {
+                if random() < weight {
+                    sample_henyey_greenstein(g1)
+                } else {
+                    sample_henyey_greenstein(g2)
+                }
+            }
+            PhaseFunction::Rayleigh => {
+                // Inverts the CDF (cos^3 + 3 cos + 4) / 8 with Cardano's formula.
+                let q = 4.0 * random() - 2.0;
+                let u = f64::cbrt(q + f64::sqrt(q * q + 1.0));
+                u - 1.0 / u
+            }
+        };
+
+        let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = 2.0 * PI * random();
+
+        let uvw = Onb::new_from_w(dir);
+        uvw.local(&Vec3::new(sin_theta * f64::cos(phi), sin_theta * f64::sin(phi), cos_theta))
+    }
+}
+
+fn sample_henyey_greenstein(g: f64) -> f64 {
+    if f64::abs(g) < 1e-3 {
+        return 1.0 - 2.0 * random();
+    }
+
+    let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * random());
+    (1.0 + g * g - s * s) / (2.0 * g)
+}
@@ -1,10 +1,14 @@
 use crate::point3::Point3;
 use crate::vec3::Vec3;
 
+#[derive(Clone)]
 pub struct Ray {
     pub origin: Point3,
     pub dir: Vec3,
     pub time: f64,
+    pub cone_width: f64,
+    pub cone_angle: f64,
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -13,9 +17,33 @@ impl Ray {
             origin,
             dir,
             time,
+            cone_width: 0.0,
+            cone_angle: 0.0,
+            wavelength: None,
         }
     }
 
+    // Tracks the footprint of the ray as a cone, used to filter texture lookups.
+    pub fn with_cone(self, cone_width: f64, cone_angle: f64) -> Ray {
+        Ray {
+            cone_width,
+            cone_angle,
+            ..self
+        }
+    }
+
+    // Hero wavelength in nanometres when rendering spectrally.
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray {
+            wavelength,
+            ..self
+        }
+    }
+
+    pub fn cone_width_at(&self, t: f64) -> f64 {
+        self.cone_width + self.cone_angle * t * self.dir.length()
+    }
+
     pub fn origin(&self) -> Point3 {
         self.origin
     }
@@ -0,0 +1,268 @@
+use crate::color::{clamp, Color};
+use crate::random::random;
+use crate::vec3::Vec3;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 730.0;
+
+const XYZ_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+fn piecewise_gaussian(lambda: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+    f64::exp(-0.5 * t * t)
+}
+
+// CIE 1931 2-degree colour matching functions, using the multi-lobe fit of Wyman, Sloan and
+// Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+            + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+            + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+            + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_linear_srgb(xyz: &Vec3) -> Color {
+    let m = &XYZ_TO_LINEAR_SRGB;
+    Color::new(
+        m[0][0] * xyz.x() + m[0][1] * xyz.y() + m[0][2] * xyz.z(),
+        m[1][0] * xyz.x() + m[1][1] * xyz.y() + m[1][2] * xyz.z(),
+        m[2][0] * xyz.x() + m[2][1] * xyz.y() + m[2][2] * xyz.z(),
+    )
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Smooth red, green and blue spectra that add up to one at every wavelength, so an RGB reflectance
+// in [0, 1] upsamples to a spectrum in [0, 1] and white stays flat.
+fn rgb_basis(lambda: f64) -> Vec3 {
+    let red = smoothstep(570.0, 610.0, lambda);
+    let blue = 1.0 - smoothstep(480.0, 520.0, lambda);
+    Vec3::new(red, 1.0 - red - blue, blue)
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+
+    let mut inv = [[0.0; 3]; 3];
+    for (i, row) in inv.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            let (a, b) = ((j + 1) % 3, (j + 2) % 3);
+            let (c, d) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[a][c] * m[b][d] - m[a][d] * m[b][c]) / det;
+        }
+    }
+    inv
+}
+
+// Draws wavelengths for spectral paths. Illuminants are upsampled through the same basis as
+// reflectances, but with coefficients solved so that they convert back to the RGB they came from.
+pub struct SpectralSampler {
+    y_integral: f64,
+    illuminant_coefficients: [[f64; 3]; 3],
+}
+
+impl SpectralSampler {
+    pub fn new() -> Self {
+        let mut y_integral = 0.0;
+        let mut basis_xyz = [Vec3::new(0.0, 0.0, 0.0); 3];
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            let xyz = cie_xyz(lambda);
+            let basis = rgb_basis(lambda);
+            y_integral += xyz.y();
+            for (k, b) in basis_xyz.iter_mut().enumerate() {
+                *b += basis[k] * xyz;
+            }
+            lambda += 1.0;
+        }
+
+        let mut basis_rgb = [[0.0; 3]; 3];
+        for (k, b) in basis_xyz.iter().enumerate() {
+            let rgb = xyz_to_linear_srgb(&(*b / y_integral));
+            for (j, row) in basis_rgb.iter_mut().enumerate() {
+                row[k] = rgb[j];
+            }
+        }
+
+        SpectralSampler {
+            y_integral,
+            illuminant_coefficients: invert(&basis_rgb),
+        }
+    }
+
+    // Hero wavelength sampling: one uniformly chosen wavelength and two more spread evenly after it
+    // across the visible range.
+    pub fn sample(&self) -> Wavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random() * range;
+        let mut lambda = [0.0; 3];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            *l = LAMBDA_MIN + (hero + i as f64 * range / 3.0) % range;
+        }
+
+        Wavelengths {
+            lambda,
+            y_integral: self.y_integral,
+            illuminant_coefficients: self.illuminant_coefficients,
+        }
+    }
+}
+
+// The wavelengths carried by one path. Spectral quantities along the path are stored in a Color,
+// one channel per wavelength, with the hero wavelength first.
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    y_integral: f64,
+    illuminant_coefficients: [[f64; 3]; 3],
+}
+
+impl Wavelengths {
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn reflectance(&self, rgb: &Color) -> Color {
+        self.evaluate(rgb)
+    }
+
+    pub fn illuminant(&self, rgb: &Color) -> Color {
+        let m = &self.illuminant_coefficients;
+        let coefficients = Vec3::new(
+            m[0][0] * rgb.x() + m[0][1] * rgb.y() + m[0][2] * rgb.z(),
+            m[1][0] * rgb.x() + m[1][1] * rgb.y() + m[1][2] * rgb.z(),
+            m[2][0] * rgb.x() + m[2][1] * rgb.y() + m[2][2] * rgb.z(),
+        );
+        self.evaluate(&coefficients)
+    }
+
+    fn evaluate(&self, coefficients: &Vec3) -> Color {
+        let mut spectrum = [0.0; 3];
+        for (s, lambda) in spectrum.iter_mut().zip(self.lambda.iter()) {
+            *s = Vec3::dot(coefficients, &rgb_basis(*lambda));
+        }
+        Color::new(spectrum[0], spectrum[1], spectrum[2])
+    }
+
+    // Converts radiance at these wavelengths into a linear sRGB estimate.
+    pub fn to_rgb(&self, spectrum: &Color) -> Color {
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for (i, lambda) in self.lambda.iter().enumerate() {
+            xyz += spectrum[i] * cie_xyz(*lambda);
+        }
+        xyz_to_linear_srgb(&(xyz / (3.0 * pdf * self.y_integral)))
+    }
+}
+
+// Wavelength-dependent index of refraction, with wavelengths in nanometres.
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Dense flint glass, which disperses strongly.
+    pub fn flint() -> Self {
+        Dispersion::Cauchy { a: 1.7, b: 0.0135 }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier { b: [0.3306, 4.3356, 0.0], c: [0.030625, 0.011236, 0.0] }
+    }
+
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + b.iter().zip(c.iter()).map(|(b, c)| b * l2 / (l2 - c)).sum::<f64>();
+                f64::sqrt(n2)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Averages `f` over hero wavelengths spread evenly across the visible range.
+    fn integrate(f: impl Fn(&Wavelengths) -> Color) -> Color {
+        let sampler = SpectralSampler::new();
+        let n = 350;
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for k in 0..n {
+            let hero = (k as f64 + 0.5) / n as f64 * range;
+            let mut lambda = [0.0; 3];
+            for (i, l) in lambda.iter_mut().enumerate() {
+                *l = LAMBDA_MIN + (hero + i as f64 * range / 3.0) % range;
+            }
+            let wavelengths = Wavelengths {
+                lambda,
+                y_integral: sampler.y_integral,
+                illuminant_coefficients: sampler.illuminant_coefficients,
+            };
+            sum += f(&wavelengths);
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn white_reflectance_is_flat() {
+        let sampler = SpectralSampler::new();
+        for _ in 0..100 {
+            let spectrum = sampler.sample().reflectance(&Color::new(1.0, 1.0, 1.0));
+            assert!((spectrum - Color::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn illuminants_project_back_to_their_rgb() {
+        for rgb in [Color::new(1.0, 1.0, 1.0), Color::new(0.8, 0.3, 0.1), Color::new(4.0, 4.0, 4.0)].iter() {
+            let round_trip = integrate(|w| w.to_rgb(&w.illuminant(rgb)));
+            assert!((round_trip - *rgb).length() < 1e-2 * rgb.length());
+        }
+    }
+
+    #[test]
+    fn white_surfaces_under_white_light_stay_white() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let round_trip = integrate(|w| w.to_rgb(&(w.illuminant(&white) * w.reflectance(&white))));
+        assert!((round_trip - white).length() < 1e-2);
+    }
+
+    #[test]
+    fn index_of_refraction_falls_with_wavelength() {
+        for dispersion in [Dispersion::flint(), Dispersion::diamond()].iter() {
+            let mut previous = f64::INFINITY;
+            let mut lambda = LAMBDA_MIN;
+            while lambda <= LAMBDA_MAX {
+                let ior = dispersion.ior(lambda);
+                assert!(ior < previous);
+                previous = ior;
+                lambda += 10.0;
+            }
+        }
+
+        // Diamond at the sodium D line.
+        assert!(f64::abs(Dispersion::diamond().ior(589.3) - 2.417) < 0.005);
+    }
+}
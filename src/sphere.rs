@@ -23,13 +23,22 @@ impl Sphere {
     }
 }
 
-fn get_sphere_uv(p: &Point3, u: &mut f64, v: &mut f64) {
+pub fn get_sphere_uv(p: &Point3, u: &mut f64, v: &mut f64) {
     let theta = f64::acos(-p.y());
     let phi = f64::atan2(-p.z(), p.x()) + std::f64::consts::PI;
     *u = phi / (2.0 * std::f64::consts::PI);
     *v = theta / std::f64::consts::PI;
 }
 
+pub fn get_sphere_tangents(p: &Point3, radius: f64, dpdu: &mut Vec3, dpdv: &mut Vec3) {
+    *dpdu = 2.0 * std::f64::consts::PI * radius * Vec3::new(p.z(), 0.0, -p.x());
+    *dpdv = if dpdu.length_squared() > 0.0 {
+        std::f64::consts::PI * radius * Vec3::cross(p, &Vec3::unit_vector(*dpdu))
+    } else {
+        Vec3::new(0.0, std::f64::consts::PI * radius, 0.0)
+    };
+}
+
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
         let oc = r.origin() - self.center;
@@ -48,6 +57,7 @@ impl Hittable for Sphere {
                 let outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(r, &outward_normal);
                 get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+                get_sphere_tangents(&outward_normal, self.radius, &mut rec.tangent, &mut rec.bitangent);
                 rec.mat_ptr = Some(self.mat_ptr.clone());
                 return true;
             }
@@ -59,12 +69,13 @@ impl Hittable for Sphere {
                 let outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(r, &outward_normal);
                 get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+                get_sphere_tangents(&outward_normal, self.radius, &mut rec.tangent, &mut rec.bitangent);
                 rec.mat_ptr = Some(self.mat_ptr.clone());
                 return true;
             }
         }
 
-        return false;
+        false
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
@@ -0,0 +1,146 @@
+use std::rc::Rc;
+
+use crate::camera::Projection;
+use crate::color::clamp;
+use crate::point3::Point3;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy)]
+pub enum StereoLayout {
+    // Left eye on the left half of the image.
+    SideBySide,
+    // Left eye on the top half of the image.
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "side-by-side" => Some(StereoLayout::SideBySide),
+            "top-bottom" => Some(StereoLayout::TopBottom),
+            _ => None,
+        }
+    }
+}
+
+// Renders both eyes of a stereo pair into one image. Each eye sees through `eye`, offset by half the
+// interpupillary distance, with parallel axes and the image shifted so that both eyes agree at the
+// convergence distance.
+//
+// With `omnidirectional` set the eyes are offset across each ray instead of across the view, which
+// gives omni-directional stereo for panoramic projections. The offset fades out towards the poles.
+//
+// `image_size` is the size of the whole image in pixels, which decides the eye a sample belongs to.
+pub struct StereoRig {
+    eye: Rc<dyn Projection>,
+    layout: StereoLayout,
+    image_size: (u32, u32),
+    ipd: f64,
+    convergence: f64,
+    omnidirectional: bool,
+}
+
+impl StereoRig {
+    pub fn new(eye: Rc<dyn Projection>, layout: StereoLayout, image_size: (u32, u32), ipd: f64, convergence: f64, omnidirectional: bool) -> Self {
+        StereoRig {
+            eye,
+            layout,
+            image_size,
+            ipd,
+            convergence,
+            omnidirectional,
+        }
+    }
+
+    pub fn image_size(layout: StereoLayout, eye_width: u32, eye_aspect_ratio: f64) -> (u32, f64) {
+        match layout {
+            StereoLayout::SideBySide => (2 * eye_width, 2.0 * eye_aspect_ratio),
+            StereoLayout::TopBottom => (eye_width, eye_aspect_ratio / 2.0),
+        }
+    }
+}
+
+impl Projection for StereoRig {
+    fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+        // Samples belong to the eye whose half holds their pixel, and each half is stretched over the
+        // eye's whole view. Samples from the far side of a pixel on the split stay at the eye's edge.
+        let (width, height) = (self.image_size.0, self.image_size.1);
+        let (left, s, t) = match self.layout {
+            StereoLayout::SideBySide => {
+                let left = s * ((width - 1) as f64) < (width / 2) as f64;
+                let eye_index = if left { 0.0 } else { 1.0 };
+                (left, clamp(2.0 * s - eye_index, 0.0, 1.0), t)
+            }
+            StereoLayout::TopBottom => {
+                // Rows count up from the bottom of the image here.
+                let left = t * (height - 1) as f64 >= (height - height / 2) as f64;
+                let eye_index = if left { 1.0 } else { 0.0 };
+                (left, s, clamp(2.0 * t - eye_index, 0.0, 1.0))
+            }
+        };
+        let (origin, direction) = self.eye.generate_ray(s, t)?;
+
+        let half_ipd = if left { -self.ipd / 2.0 } else { self.ipd / 2.0 };
+        let (offset, target) = if self.omnidirectional {
+            let offset = half_ipd * Vec3::new(-direction.z(), 0.0, direction.x()) / direction.length();
+            (offset, origin + self.convergence * Vec3::unit_vector(direction))
+        } else {
+            let offset = Vec3::new(half_ipd, 0.0, 0.0);
+            (offset, origin + direction * (self.convergence / -direction.z()))
+        };
+
+        let eye_origin = origin + offset;
+        Some((eye_origin, target - eye_origin))
+    }
+
+    fn pixel_footprint(&self, image_height: u32) -> (f64, f64) {
+        match self.layout {
+            StereoLayout::SideBySide => self.eye.pixel_footprint(image_height),
+            StereoLayout::TopBottom => self.eye.pixel_footprint(image_height / 2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Looks down -z, with the view spanning [0, 1] on both axes at unit distance.
+    struct Pinhole;
+
+    impl Projection for Pinhole {
+        fn generate_ray(&self, s: f64, t: f64) -> Option<(Point3, Vec3)> {
+            Some((Point3::new(0.0, 0.0, 0.0), Vec3::new(s, t, -1.0)))
+        }
+
+        fn pixel_footprint(&self, _image_height: u32) -> (f64, f64) {
+            (0.0, 0.0)
+        }
+    }
+
+    // Which eye a sample went to, and where it landed in that eye's view.
+    fn eye_sample(rig: &StereoRig, s: f64, t: f64) -> (bool, f64, f64) {
+        let (origin, direction) = rig.generate_ray(s, t).unwrap();
+        let target = origin + direction;
+        (origin.x() < 0.0, target.x(), target.y())
+    }
+
+    #[test]
+    fn edge_samples_stay_in_their_eye() {
+        // Ten pixels across: 0-4 are the left eye and 5-9 the right.
+        let rig = StereoRig::new(Rc::new(Pinhole), StereoLayout::SideBySide, (10, 5), 0.2, 1.0, false);
+        let (left, s, _) = eye_sample(&rig, (4.0 + 0.999) / 9.0, 0.5);
+        assert!(left && f64::abs(s - 1.0) < 1e-9);
+        let (left, s, _) = eye_sample(&rig, 5.0 / 9.0, 0.5);
+        assert!(!left && s > 0.0 && s < 0.2);
+        let (left, s, _) = eye_sample(&rig, (9.0 + 0.999) / 9.0, 0.5);
+        assert!(!left && f64::abs(s - 1.0) < 1e-9);
+
+        // Ten rows, counted up from the bottom: 5-9 are the left eye at the top.
+        let rig = StereoRig::new(Rc::new(Pinhole), StereoLayout::TopBottom, (5, 10), 0.2, 1.0, false);
+        let (left, _, t) = eye_sample(&rig, 0.5, (4.0 + 0.999) / 9.0);
+        assert!(!left && f64::abs(t - 1.0) < 1e-9);
+        let (left, _, t) = eye_sample(&rig, 0.5, 5.0 / 9.0);
+        assert!(left && t > 0.0 && t < 0.2);
+    }
+}
@@ -6,12 +6,28 @@ use image::{DynamicImage, GenericImageView};
 use color::clamp;
 
 use crate::color;
-use crate::color::Color;
+use crate::color::{Color, ColorEncoding};
+use crate::hittable::HitRecord;
 use crate::perlin::Perlin;
 use crate::point3::Point3;
 
 pub trait Texture {
-    fn value(self: &Self, u: f64, v: f64, p: &Point3) -> Color;
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    fn scalar_value(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        let c = self.value(u, v, p);
+        (c.x() + c.y() + c.z()) / 3.0
+    }
+
+    // `du` and `dv` give the extent of the lookup in texture space; textures that can't filter ignore them.
+    fn value_filtered(&self, u: f64, v: f64, p: &Point3, _du: f64, _dv: f64) -> Color {
+        self.value(u, v, p)
+    }
+
+    fn sample(&self, rec: &HitRecord) -> Color {
+        let (du, dv) = rec.uv_footprint();
+        self.value_filtered(rec.u, rec.v, &rec.p, du, dv)
+    }
 }
 
 pub struct SolidColor {
@@ -24,14 +40,45 @@ impl SolidColor {
             color_value
         }
     }
+
+    pub fn new_scalar(value: f64) -> Self {
+        SolidColor::new(Color::new(value, value, value))
+    }
 }
 
 impl Texture for SolidColor {
-    fn value(self: &Self, _u: f64, _v: f64, _p: &Point3) -> Color {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         self.color_value
     }
 }
 
+pub struct RemapTexture {
+    texture: Rc<dyn Texture>,
+    min: f64,
+    max: f64,
+}
+
+impl RemapTexture {
+    pub fn new(texture: Rc<dyn Texture>, min: f64, max: f64) -> Self {
+        RemapTexture {
+            texture,
+            min,
+            max,
+        }
+    }
+}
+
+impl Texture for RemapTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.value_filtered(u, v, p, 0.0, 0.0)
+    }
+
+    fn value_filtered(&self, u: f64, v: f64, p: &Point3, du: f64, dv: f64) -> Color {
+        let t = self.texture.value_filtered(u, v, p, du, dv);
+        Color::new(self.min, self.min, self.min) + (self.max - self.min) * t
+    }
+}
+
 pub struct CheckerTexture {
     odd: Rc<dyn Texture>,
     even: Rc<dyn Texture>,
@@ -47,7 +94,7 @@ impl CheckerTexture {
 }
 
 impl Texture for CheckerTexture {
-    fn value(self: &Self, u: f64, v: f64, p: &Point3) -> Color {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
         let sines = f64::sin(10.0 * p.x()) * f64::sin(10.0 * p.y()) * f64::sin(10.0 * p.z());
         if sines < 0.0 {
             self.odd.value(u, v, p)
@@ -72,36 +119,373 @@ impl NoiseTexture {
 }
 
 impl Texture for NoiseTexture {
-    fn value(self: &Self, _u: f64, _v: f64, p: &Point3) -> Color {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
         Color::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + f64::sin(self.scale * p.z() + 10.0 * self.noise.turb(p, Option::None)))
     }
 }
 
+pub struct TurbulenceTexture {
+    noise: Perlin,
+    scale: f64,
+    depth: i32,
+}
+
+impl TurbulenceTexture {
+    pub fn new(scale: f64, depth: i32) -> Self {
+        TurbulenceTexture {
+            noise: Perlin::new(),
+            scale,
+            depth,
+        }
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        Color::new(1.0, 1.0, 1.0) * self.noise.turb(&(self.scale * p), Some(self.depth))
+    }
+}
+
+// A 3D grid of values spanning the box from `min` to `max`, looked up by position with trilinear
+// interpolation. Points outside the box are black.
+pub struct GridTexture {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    min: Point3,
+    max: Point3,
+    values: Vec<Color>,
+}
+
+impl GridTexture {
+    // `values` is indexed by `x + nx * (y + ny * z)`.
+    pub fn new(nx: usize, ny: usize, nz: usize, min: Point3, max: Point3, values: Vec<Color>) -> Self {
+        assert_eq!(values.len(), nx * ny * nz);
+
+        GridTexture {
+            nx,
+            ny,
+            nz,
+            min,
+            max,
+            values,
+        }
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> Color {
+        self.values[i + self.nx * (j + self.ny * k)]
+    }
+}
+
+impl Texture for GridTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let n = [self.nx, self.ny, self.nz];
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for a in 0..3 {
+            let t = (p[a] - self.min[a]) / (self.max[a] - self.min[a]);
+            if !(0.0..=1.0).contains(&t) {
+                return Color::new(0.0, 0.0, 0.0);
+            }
+
+            let x = t * (n[a] - 1) as f64;
+            base[a] = usize::min(x as usize, n[a].saturating_sub(2));
+            frac[a] = x - base[a] as f64;
+        }
+
+        let mut accum = Color::new(0.0, 0.0, 0.0);
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let i = usize::min(base[0] + di, self.nx - 1);
+                    let j = usize::min(base[1] + dj, self.ny - 1);
+                    let k = usize::min(base[2] + dk, self.nz - 1);
+                    let weight = (if di == 1 { frac[0] } else { 1.0 - frac[0] })
+                        * (if dj == 1 { frac[1] } else { 1.0 - frac[1] })
+                        * (if dk == 1 { frac[2] } else { 1.0 - frac[2] });
+                    accum += weight * self.voxel(i, j, k);
+                }
+            }
+        }
+
+        accum
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    fn wrap(&self, i: i64, n: i64) -> i64 {
+        match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m >= n {
+                    2 * n - 1 - m
+                } else {
+                    m
+                }
+            }
+            WrapMode::Clamp => clamp(i, 0, n - 1),
+        }
+    }
+}
+
+// The texels, and their weights, averaged into texel `i` of the next level down along a side `n`
+// texels long. Odd sides spread three texels over each one, so that every texel counts equally.
+fn downsample_taps(i: i64, n: i64) -> Vec<(i64, f64)> {
+    if n == 1 {
+        vec![(0, 1.0)]
+    } else if n % 2 == 0 {
+        vec![(2 * i, 0.5), (2 * i + 1, 0.5)]
+    } else {
+        let half = (n / 2) as f64;
+        let n = n as f64;
+        vec![(2 * i, (half - i as f64) / n), (2 * i + 1, half / n), (2 * i + 2, (i + 1) as f64 / n)]
+    }
+}
+
+struct MipLevel {
+    width: i64,
+    height: i64,
+    texels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn new_from_image(img: &DynamicImage, encoding: ColorEncoding) -> Self {
+        let color_scale = 1.0 / 255.0;
+        let texels = img.to_rgb8().pixels()
+            .map(|pixel| Color::new(
+                encoding.decode(color_scale * pixel[0] as f64),
+                encoding.decode(color_scale * pixel[1] as f64),
+                encoding.decode(color_scale * pixel[2] as f64),
+            ))
+            .collect();
+
+        MipLevel {
+            width: img.width() as i64,
+            height: img.height() as i64,
+            texels,
+        }
+    }
+
+    fn downsample(&self) -> Self {
+        let width = i64::max(1, self.width / 2);
+        let height = i64::max(1, self.height / 2);
+
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                for (tj, wj) in downsample_taps(j, self.height) {
+                    for (ti, wi) in downsample_taps(i, self.width) {
+                        sum += (wi * wj) * self.texel(ti, tj);
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Color {
+        self.texels[(j * self.width + i) as usize]
+    }
+
+    fn wrapped_texel(&self, i: i64, j: i64, wrap: WrapMode) -> Color {
+        self.texel(wrap.wrap(i, self.width), wrap.wrap(j, self.height))
+    }
+
+    fn lookup(&self, u: f64, v: f64, interpolation: Interpolation, wrap: WrapMode) -> Color {
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+
+        match interpolation {
+            Interpolation::Nearest => self.wrapped_texel(f64::floor(x) as i64, f64::floor(y) as i64, wrap),
+            Interpolation::Bilinear => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let i = f64::floor(x) as i64;
+                let j = f64::floor(y) as i64;
+                let fx = x - f64::floor(x);
+                let fy = y - f64::floor(y);
+
+                (1.0 - fx) * (1.0 - fy) * self.wrapped_texel(i, j, wrap)
+                    + fx * (1.0 - fy) * self.wrapped_texel(i + 1, j, wrap)
+                    + (1.0 - fx) * fy * self.wrapped_texel(i, j + 1, wrap)
+                    + fx * fy * self.wrapped_texel(i + 1, j + 1, wrap)
+            }
+            Interpolation::Bicubic => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let i = f64::floor(x) as i64;
+                let j = f64::floor(y) as i64;
+                let wx = catmull_rom_weights(x - f64::floor(x));
+                let wy = catmull_rom_weights(y - f64::floor(y));
+
+                let mut accum = Color::new(0.0, 0.0, 0.0);
+                for (dj, wy) in wy.iter().enumerate() {
+                    for (di, wx) in wx.iter().enumerate() {
+                        accum += (wx * wy) * self.wrapped_texel(i + di as i64 - 1, j + dj as i64 - 1, wrap);
+                    }
+                }
+
+                Color::new(f64::max(accum.x(), 0.0), f64::max(accum.y(), 0.0), f64::max(accum.z(), 0.0))
+            }
+        }
+    }
+}
+
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
 pub struct ImageTexture {
-    img: DynamicImage
+    levels: Vec<MipLevel>,
+    interpolation: Interpolation,
+    wrap: WrapMode,
+    uv_scale: (f64, f64),
+    uv_offset: (f64, f64),
 }
 
 impl ImageTexture {
     pub fn new(filename: &Path) -> Self {
+        ImageTexture::new_with_encoding(filename, ColorEncoding::Srgb)
+    }
+
+    // Data textures such as normal or roughness maps should be loaded with `ColorEncoding::Linear`.
+    pub fn new_with_encoding(filename: &Path, encoding: ColorEncoding) -> Self {
+        ImageTexture::open(filename, encoding).unwrap()
+    }
+
+    // Like `new_with_encoding`, but reports a file that can't be read or decoded instead of panicking.
+    pub fn open(filename: &Path, encoding: ColorEncoding) -> Result<Self, String> {
+        let img = image::io::Reader::open(filename)
+            .map_err(|e| format!("Can't open {}: {}", filename.display(), e))?
+            .decode()
+            .map_err(|e| format!("Can't decode {}: {}", filename.display(), e))?;
+
+        Ok(ImageTexture {
+            levels: vec![MipLevel::new_from_image(&img, encoding)],
+            interpolation: Interpolation::Nearest,
+            wrap: WrapMode::Clamp,
+            uv_scale: (1.0, 1.0),
+            uv_offset: (0.0, 0.0),
+        })
+    }
+
+    pub fn with_interpolation(self, interpolation: Interpolation) -> Self {
+        ImageTexture {
+            interpolation,
+            ..self
+        }
+    }
+
+    pub fn with_wrap(self, wrap: WrapMode) -> Self {
+        ImageTexture {
+            wrap,
+            ..self
+        }
+    }
+
+    pub fn with_uv_transform(self, scale: (f64, f64), offset: (f64, f64)) -> Self {
+        ImageTexture {
+            uv_scale: scale,
+            uv_offset: offset,
+            ..self
+        }
+    }
+
+    // Builds the mip pyramid, so lookups blend between the two levels that best match the ray footprint.
+    pub fn with_mipmaps(self) -> Self {
+        let mut levels = self.levels;
+        levels.truncate(1);
+        loop {
+            let last = levels.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+
         ImageTexture {
-            img: image::io::Reader::open(filename).unwrap().decode().unwrap()
+            levels,
+            ..self
         }
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(self: &Self, u: f64, v: f64, _p: &Point3) -> Color {
-        let u = clamp(u, 0.0, 1.0);
-        let width = self.img.width();
-        let i = clamp((u * width as f64) as u32, 0, width - 1);
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.value_filtered(u, v, p, 0.0, 0.0)
+    }
 
-        let v = 1.0 - clamp(v, 0.0, 1.0);
-        let height = self.img.height();
-        let j = clamp((v * height as f64) as u32, 0, height - 1);
+    fn value_filtered(&self, u: f64, v: f64, _p: &Point3, du: f64, dv: f64) -> Color {
+        let u = u * self.uv_scale.0 + self.uv_offset.0;
+        let v = v * self.uv_scale.1 + self.uv_offset.1;
 
-        let color_scale = 1.0 / 255.0;
-        let pixel = self.img.get_pixel(i, j);
+        let base = &self.levels[0];
+        let width = f64::max(du * self.uv_scale.0.abs() * base.width as f64, dv * self.uv_scale.1.abs() * base.height as f64);
+        let max_level = (self.levels.len() - 1) as f64;
+        let level = if width > 1.0 {
+            f64::min(f64::log2(width), max_level)
+        } else {
+            0.0
+        };
+
+        let lower = f64::floor(level) as usize;
+        let upper = f64::ceil(level) as usize;
+        let lower_value = self.levels[lower].lookup(u, v, self.interpolation, self.wrap);
+        if lower == upper {
+            return lower_value;
+        }
 
-        Color::new(color_scale * pixel[0] as f64, color_scale * pixel[1] as f64, color_scale * pixel[2] as f64)
+        let t = level - lower as f64;
+        (1.0 - t) * lower_value + t * self.levels[upper].lookup(u, v, self.interpolation, self.wrap)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean(level: &MipLevel) -> Color {
+        level.texels.iter().fold(Color::new(0.0, 0.0, 0.0), |sum, t| sum + *t) / level.texels.len() as f64
+    }
+
+    #[test]
+    fn downsampling_odd_sizes_keeps_every_texel() {
+        for &(width, height) in [(5, 3), (4, 7), (1, 5), (6, 6)].iter() {
+            let texels = (0..width * height).map(|k| Color::new(k as f64, (k * k % 7) as f64, 1.0)).collect();
+            let level = MipLevel { width, height, texels };
+            let next = level.downsample();
+            assert_eq!((next.width, next.height), (i64::max(1, width / 2), i64::max(1, height / 2)));
+            assert!((mean(&next) - mean(&level)).length() < 1e-9);
+        }
+    }
+}
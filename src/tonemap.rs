@@ -0,0 +1,138 @@
+use crate::color::{clamp, mul, Color};
+
+#[derive(Clone, Copy)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard(f64),
+    AcesFilmic,
+    Agx,
+}
+
+// AgX is defined on Rec.2020 primaries, so colours are converted into them and back.
+const LINEAR_SRGB_TO_REC2020: [[f64; 3]; 3] = [
+    [0.6274039, 0.3292830, 0.0433131],
+    [0.0690973, 0.9195404, 0.0113623],
+    [0.0163914, 0.0880133, 0.8955953],
+];
+
+const REC2020_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [1.6604910, -0.5876411, -0.0728499],
+    [-0.1245505, 1.1328999, -0.0083494],
+    [-0.0181508, -0.1005789, 1.1187297],
+];
+
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.856627153315983, 0.0951212405381588, 0.0482516061458583],
+    [0.137318972929847, 0.761241990602591, 0.101439036467562],
+    [0.11189821299995, 0.0767994186031903, 0.811302368396859],
+];
+
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.1271005818144368, -0.11060664309660323, -0.016493938717834573],
+    [-0.1413297634984383, 1.157823702216272, -0.016493938717834257],
+    [-0.14132976349843826, -0.11060664309660294, 1.2519364065950405],
+];
+
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+fn map_channels(c: &Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+fn scale_luminance(c: &Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = luminance(c);
+    if l <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    (f(l) / l) * *c
+}
+
+fn agx_contrast(x: f64) -> f64 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str, white: f64) -> Option<Self> {
+        match name {
+            "clamp" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "reinhard-extended" => Some(ToneMapOperator::ExtendedReinhard(white)),
+            "aces" => Some(ToneMapOperator::AcesFilmic),
+            "agx" => Some(ToneMapOperator::Agx),
+            _ => None,
+        }
+    }
+
+    // Maps scene-referred linear sRGB to display-referred linear sRGB in [0, 1].
+    pub fn apply(&self, c: &Color) -> Color {
+        let mapped = match self {
+            ToneMapOperator::Clamp => *c,
+            ToneMapOperator::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMapOperator::ExtendedReinhard(white) => {
+                scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapOperator::AcesFilmic => {
+                map_channels(c, |x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14))
+            }
+            ToneMapOperator::Agx => {
+                let c = mul(&AGX_INSET, &mul(&LINEAR_SRGB_TO_REC2020, c));
+                let c = map_channels(&c, |x| {
+                    let ev = f64::log2(f64::max(x, 1e-10));
+                    agx_contrast(clamp((ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV), 0.0, 1.0))
+                });
+                let c = mul(&AGX_OUTSET, &c);
+                let c = map_channels(&c, |x| f64::powf(f64::max(x, 0.0), 2.2));
+                mul(&REC2020_TO_LINEAR_SRGB, &c)
+            }
+        };
+
+        map_channels(&mapped, |x| clamp(x, 0.0, 1.0))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f64,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator, exposure: f64) -> Self {
+        ToneMapping {
+            operator,
+            exposure,
+        }
+    }
+
+    pub fn apply(&self, c: &Color) -> Color {
+        self.operator.apply(&(f64::powf(2.0, self.exposure) * *c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rec2020_conversions_invert_each_other() {
+        let c = Color::new(0.3, 0.7, 0.1);
+        let round_trip = mul(&REC2020_TO_LINEAR_SRGB, &mul(&LINEAR_SRGB_TO_REC2020, &c));
+        assert!((round_trip - c).length() < 1e-6);
+    }
+
+    #[test]
+    fn agx_keeps_greys_neutral() {
+        for &grey in [0.01, 0.18, 1.0, 10.0].iter() {
+            let c = ToneMapOperator::Agx.apply(&Color::new(grey, grey, grey));
+            assert!(f64::abs(c.x() - c.y()) < 1e-4 && f64::abs(c.y() - c.z()) < 1e-4);
+        }
+    }
+}